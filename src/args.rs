@@ -1,19 +1,113 @@
 use simplelog::LevelFilter;
 use structopt::StructOpt;
+use std::time::Duration;
+use crate::logic::{
+    clock::TimeControl,
+    color::PieceColor,
+};
 
-#[derive(StructOpt)]
+#[derive(StructOpt, Clone)]
 /// A modifiable chess game
 pub struct Arguments {
     /// Port to host server on
     #[structopt(short, long, default_value="1126")]
     pub port: u16,
-    /// Lua file for game
+    /// Rhai file for the default game
     #[structopt(short, long, default_value="./game.rhai")]
     pub game: String,
+    /// Directory with rules files clients can create games from
+    #[structopt(long, default_value="./games")]
+    pub games_dir: String,
     /// Hide startup banner
     #[structopt(long)]
     pub no_startup_banner: bool,
+    /// Time control for games whose rules don't set one, written as `<base>+<increment>`,
+    /// `<base>d<delay>` or `<time>/move` in seconds
+    #[structopt(short, long)]
+    pub time_control: Option<TimeControl>,
+    /// Seconds the seat of a disconnected player is kept before the player forfeits
+    #[structopt(long, default_value="60")]
+    pub grace_period: u64,
+    /// Directory games are saved to after every move and when the server shuts down
+    #[structopt(long)]
+    pub save_dir: Option<String>,
+    /// Directory the records of games are written to
+    #[structopt(long)]
+    pub record_dir: Option<String>,
+    /// Save file to restore a game from when the server starts
+    #[structopt(long)]
+    pub resume: Option<String>,
+    /// Colors played by bots in the default game
+    #[structopt(long)]
+    pub bot: Vec<PieceColor>,
+    /// Number of moves bots look ahead
+    #[structopt(long, default_value="3")]
+    pub bot_depth: usize,
+    /// Most moves a bot requested by a client can look ahead
+    #[structopt(long, default_value="6")]
+    pub max_bot_depth: usize,
+    /// Seconds bots can search for a move, which is also the most a client can ask for
+    #[structopt(long, default_value="5", parse(try_from_str = parse_seconds))]
+    pub bot_time: Duration,
     /// Loglevel
     #[structopt(short, long, default_value="info")]
     pub loglevel: LevelFilter,
+    #[structopt(flatten)]
+    pub limits: ScriptLimits,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Limits for rhai scripts, so a mistake in a rules file can't freeze the server
+#[derive(StructOpt, Clone, Copy)]
+pub struct ScriptLimits {
+    /// Most operations a rhai script or callback can run
+    #[structopt(long, default_value="1000000")]
+    pub max_operations: u64,
+    /// Deepest nesting of rhai function calls
+    #[structopt(long, default_value="64")]
+    pub max_call_depth: usize,
+    /// Deepest nesting of rhai expressions, both at the top level of a script and in functions
+    #[structopt(long, default_value="64")]
+    pub max_expr_depth: usize,
+    /// Largest string, array or object map a rhai script can create
+    #[structopt(long, default_value="100000")]
+    pub max_script_size: usize,
+    /// Seconds a rhai script or callback can run
+    #[structopt(long, default_value="1", parse(try_from_str = parse_seconds))]
+    pub script_time: Duration,
+}
+
+/// Parses a number of seconds, rejecting times that are negative or too long for a `Duration`
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let secs: f64 = s.parse().map_err(|_| format!("Invalid number of seconds: {}", s))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| format!("Invalid number of seconds {}: {}", s, e))
+}
+
+/// Tools that are run instead of the server
+#[derive(StructOpt, Clone)]
+pub enum Command {
+    /// Counts the positions reachable from the start of a game to test move generation
+    Perft(PerftArgs),
+    /// Loads a rules file and reports problems with it, without starting a server
+    Check(CheckArgs),
+}
+
+#[derive(StructOpt, Clone)]
+pub struct PerftArgs {
+    /// Rhai file with the rules to test
+    #[structopt(short, long, default_value="./game.rhai")]
+    pub game: String,
+    /// Number of moves to look ahead
+    #[structopt(short, long)]
+    pub depth: usize,
+    /// Print the number of positions after each move
+    #[structopt(long)]
+    pub divide: bool,
+}
+
+#[derive(StructOpt, Clone)]
+pub struct CheckArgs {
+    /// Rhai file with the rules to check
+    pub game: String,
 }
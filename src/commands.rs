@@ -0,0 +1,146 @@
+use crate::{
+    Error,
+    args::{PerftArgs, CheckArgs, ScriptLimits},
+    rules::RulesEnv,
+    logic::{
+        board::GameBoard,
+        move_gen::{generate_moves, RhaiEnv},
+        perft::Perft,
+        record,
+    },
+};
+use std::time::Instant;
+
+/// Runs perft on the start position of a rules file and prints the number of positions
+pub fn perft(args: &PerftArgs, limits: &ScriptLimits) -> Result<(), Error> {
+    let env = RulesEnv::new(&args.game, limits)?;
+    let rules = env.rules.borrow();
+    let board = rules.board.borrow().clone();
+    let uses_rhai = rules.pieces.values()
+        .any(|piece| piece.extra_moves.is_some() || piece.after_move.is_some() || piece.after_take.is_some());
+    let perft = Perft {
+        pieces: &rules.pieces,
+        colors: &rules.colors,
+        rhai_env: Some(RhaiEnv { ast: &env.ast, engine: &env.engine, errors: None }),
+        shared_board: if uses_rhai { Some(rules.board.clone()) } else { None },
+        winner: Some(env.winner.clone()),
+    };
+    let start = Instant::now();
+    let (nodes, divide) = if args.divide {
+        let divide = perft.divide(&board, rules.colors[0], args.depth);
+        (divide.iter().map(|(_, nodes)| nodes).sum(), divide)
+    } else {
+        (perft.perft(&board, rules.colors[0], args.depth), vec![])
+    };
+    let secs = start.elapsed().as_secs_f64();
+    for (mov, nodes) in divide {
+        let mut name = format!("{}{}", record::square_name(mov.from, board.height), record::square_name(mov.to, board.height));
+        if let Some(promotion) = &mov.promotion {
+            name.push_str(&record::piece_name(promotion));
+        }
+        println!("{}: {}", name, nodes);
+    }
+    println!();
+    println!("Nodes: {}", nodes);
+    println!("Time: {:.3} s", secs);
+    println!("Nodes/second: {:.0}", nodes as f64 / secs.max(f64::EPSILON));
+    Ok(())
+}
+
+/// Mistakes found in rules by the check command
+#[derive(Debug, Default)]
+pub struct Problems {
+    /// Mistakes that make the rules unplayable as intended
+    pub errors: Vec<String>,
+    /// Things that are usually mistakes, but can be intended, like the missing white king in
+    /// horde chess
+    pub warnings: Vec<String>,
+}
+
+/// Loads a rules file and prints the problems found in it. Fails if there are any errors, but
+/// not for warnings
+pub fn check(args: &CheckArgs, limits: &ScriptLimits) -> Result<(), Error> {
+    let env = RulesEnv::new(&args.game, limits)?;
+    let problems = problems(&env);
+    for warning in &problems.warnings {
+        println!("Warning: {}", warning);
+    }
+    for error in &problems.errors {
+        println!("{}", error);
+    }
+    if !problems.errors.is_empty() {
+        Err(Error::Check(problems.errors.len()))
+    } else if problems.warnings.is_empty() {
+        println!("No problems found in {}", args.game);
+        Ok(())
+    } else {
+        println!("No errors found in {}", args.game);
+        Ok(())
+    }
+}
+
+/// Finds mistakes in rules that load, but can't be played as intended
+pub fn problems(env: &RulesEnv) -> Problems {
+    let rules = env.rules.borrow();
+    let board = rules.board.borrow();
+    let mut problems = Problems::default();
+    if rules.ffen.is_empty() {
+        problems.errors.push("The rules don't create a board".to_string());
+        return problems;
+    }
+    // Short rows are filled with voids, which is a mistake unless they are written out
+    if let Ok(widths) = GameBoard::ffen_row_widths(&rules.ffen) {
+        for (y, width) in widths.iter().enumerate().filter(|(_, width)| **width != board.width) {
+            problems.errors.push(format!("Row {} has {} squares, but the board is {} squares wide", board.height - y, width, board.width));
+        }
+    }
+    let mut unknown = false;
+    for (x, y) in board.get_positions_of(&|_| true) {
+        let piece = board.board[y][x].as_ref().unwrap();
+        if !rules.pieces.contains_key(&piece.symbol) {
+            problems.errors.push(format!("No piece is defined for \"{}\" on {}", piece.symbol, record::square_name((x, y), board.height)));
+            unknown = true;
+        }
+    }
+    // Moves can't be generated for pieces that aren't defined
+    if unknown {
+        return problems;
+    }
+    // A color without a king can't be checkmated, which some variants intend
+    for color in &rules.colors {
+        let kings = board.get_positions_of(&|piece| piece.color == *color && rules.pieces[&piece.symbol].kingstatus);
+        if kings.is_empty() {
+            problems.warnings.push(format!("{:?} has no king", color));
+        }
+    }
+    let functions = env.ast.iter_functions().map(|f| (f.name.to_string(), f.params.len())).collect::<Vec<_>>();
+    let mut ids = rules.pieces.keys().collect::<Vec<_>>();
+    ids.sort();
+    for id in ids {
+        let piece = &rules.pieces[id];
+        let callbacks = [("after_move", &piece.after_move, 1), ("after_take", &piece.after_take, 1), ("add_moves", &piece.extra_moves, 2)];
+        for (event, callback, params) in callbacks.iter() {
+            if let Some(callback) = callback {
+                if !functions.contains(&(callback.name.clone(), *params)) {
+                    problems.errors.push(format!("The {} callback of \"{}\" given on line {} has no function {} with {} parameters",
+                                          event, id, callback.line, callback.name, params));
+                }
+            }
+        }
+    }
+    // Each piece is checked alone on an otherwise empty board, so pieces that are only blocked by
+    // other pieces at the start aren't reported
+    for (x, y) in board.get_positions_of(&|piece| rules.pieces[&piece.symbol].extra_moves.is_none()) {
+        let mut alone = board.clone();
+        for row in alone.board.iter_mut() {
+            row.iter_mut().for_each(|square| *square = None);
+        }
+        alone.board[y][x] = board.board[y][x].clone();
+        let piece = board.board[y][x].as_ref().unwrap();
+        if generate_moves(piece.color, &rules.pieces, &alone, None).is_empty() {
+            problems.errors.push(format!("\"{}\" on {} can't move from its start square even with no other pieces on the board",
+                                  piece.symbol, record::square_name((x, y), board.height)));
+        }
+    }
+    problems
+}
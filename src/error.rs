@@ -1,12 +1,42 @@
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 /// General error type for Álfheimr
 pub enum Error {
-    /// Failed to parse Parlett
-    ParlettParse,
-    /// Failed to parse FFen
-    FFenParse,
+    /// Failed to parse Parlett "{input}" at character {position}
+    ParlettParse { input: String, position: usize },
+    /// Failed to parse Betza "{input}" at character {position}
+    BetzaParse { input: String, position: usize },
+    /// Failed to parse FFen "{input}" at character {position}
+    FFenParse { input: String, position: usize },
     /// Invalid move given to board
     InvalidMove,
-    /// Failed to evaluate rhai code
-    RhaiError(#[from] Box<rhai::plugin::EvalAltResult>)
+    /// Failed to parse time control "{0}"
+    TimeControlParse(String),
+    /// Failed to parse color "{0}"
+    ColorParse(String),
+    /// Failed to parse board geometry "{0}"
+    GeometryParse(String),
+    /// Failed to parse stalemate rule "{0}"
+    StalemateParse(String),
+    /// No piece with the id "{0}"
+    UnknownPiece(String),
+    /// Failed to evaluate rhai code: {0}
+    Rhai(#[from] Box<rhai::EvalAltResult>),
+    /// Failed to load rules from {path}: {error}
+    Rules { path: String, error: Box<rhai::EvalAltResult> },
+    /// The {callback} callback of "{piece}" given on line {line} failed: {error}
+    Callback { callback: &'static str, piece: String, line: usize, error: Box<rhai::EvalAltResult> },
+    /// Found problems in the rules: {0}
+    Check(usize),
+    /// Failed to read or write file: {0}
+    Io(#[from] std::io::Error),
+    /// Failed to read or write save: {0}
+    Save(#[from] serde_json::Error),
+}
+
+/// Gets the character a pest parser failed at, counted from 1
+pub fn parse_position<R: pest::RuleType>(error: &pest::error::Error<R>) -> usize {
+    match error.location {
+        pest::error::InputLocation::Pos(pos) => pos + 1,
+        pest::error::InputLocation::Span((start, _)) => start + 1,
+    }
 }
@@ -0,0 +1,80 @@
+use super::{Game, PlayerMessage};
+use crate::{
+    Error,
+    networking::socketdata::SocketData,
+    logic::{
+        color::PieceColor,
+        result::{GameResult, ResultReason},
+    },
+};
+use std::net::SocketAddr;
+
+impl Game<'_> {
+
+    /// Ends the game with the player giving up
+    pub(super) fn evaluate_resign(&mut self, addr: &SocketAddr) -> Result<PlayerMessage, Error> {
+        let color = match self.validate_player(addr) {
+            Ok(color) => color,
+            Err(err_msg) => return Ok(PlayerMessage::single_player(*addr, SocketData::InvalidMessage(err_msg))),
+        };
+        info!("{:?} resigned", color);
+        Ok(self.lose(color, ResultReason::Resignation))
+    }
+
+    /// Offers a draw to the other players
+    pub(super) fn evaluate_offer_draw(&mut self, addr: &SocketAddr) -> Result<PlayerMessage, Error> {
+        let color = match self.validate_player(addr) {
+            Ok(color) => color,
+            Err(err_msg) => return Ok(PlayerMessage::single_player(*addr, SocketData::InvalidMessage(err_msg))),
+        };
+        if self.draw_offers.contains(&color) {
+            return Ok(PlayerMessage::single_player(*addr, SocketData::InvalidMessage(
+                        String::from("You have already offered a draw"))));
+        }
+        info!("{:?} offered a draw", color);
+        Ok(self.agree_to_draw(color))
+    }
+
+    /// Accepts a draw offered by another player
+    pub(super) fn evaluate_accept_draw(&mut self, addr: &SocketAddr) -> Result<PlayerMessage, Error> {
+        let color = match self.validate_draw_answer(addr) {
+            Ok(color) => color,
+            Err(err_msg) => return Ok(PlayerMessage::single_player(*addr, SocketData::InvalidMessage(err_msg))),
+        };
+        info!("{:?} accepted a draw", color);
+        Ok(self.agree_to_draw(color))
+    }
+
+    /// Declines a draw offered by another player
+    pub(super) fn evaluate_decline_draw(&mut self, addr: &SocketAddr) -> Result<PlayerMessage, Error> {
+        let color = match self.validate_draw_answer(addr) {
+            Ok(color) => color,
+            Err(err_msg) => return Ok(PlayerMessage::single_player(*addr, SocketData::InvalidMessage(err_msg))),
+        };
+        info!("{:?} declined a draw", color);
+        self.draw_offers.clear();
+        Ok(PlayerMessage::all_players(SocketData::DrawDeclined(color as u8)))
+    }
+
+    /// Validates that a player can answer a draw offer and returns the color of the player or an
+    /// error message
+    fn validate_draw_answer(&self, addr: &SocketAddr) -> Result<PieceColor, String> {
+        let color = self.validate_player(addr)?;
+        if self.draw_offers.is_empty() {
+            Err(String::from("No draw has been offered"))
+        } else if self.draw_offers.contains(&color) {
+            Err(String::from("You have already agreed to a draw"))
+        } else {
+            Ok(color)
+        }
+    }
+
+    /// Adds a player to the players agreeing to a draw, and ends the game if every player agrees
+    fn agree_to_draw(&mut self, color: PieceColor) -> PlayerMessage {
+        self.draw_offers.push(color);
+        if self.active_colors().iter().all(|c| self.draw_offers.contains(c)) {
+            return self.end_game(GameResult::Draw(ResultReason::Agreement));
+        }
+        PlayerMessage::all_players(SocketData::DrawOffer(color as u8))
+    }
+}
@@ -0,0 +1,163 @@
+use super::{Game, PlayerMessage};
+use crate::{
+    rules,
+    networking::socketdata::SocketData,
+    logic::{
+        color::PieceColor,
+        result::GameResult,
+        search::{self, SearchLimits, RootMove},
+        move_gen::GameMove,
+    },
+};
+use tokio::{
+    task,
+    sync::oneshot::{self, error::TryRecvError},
+};
+use std::time::{Duration, Instant};
+
+/// How often a game checks if the search of a bot has finished
+const BOT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Search for the move of a bot running on a blocking thread
+pub(super) struct BotSearch {
+    /// Color of the bot searching
+    color: PieceColor,
+    /// Number of moves made in the game when the search started
+    moves_made: usize,
+    /// Receives the best move found
+    receiver: oneshot::Receiver<Option<GameMove>>,
+    /// When to check next if the search has finished
+    pub(super) poll_time: Instant,
+}
+
+impl Game<'_> {
+
+    /// Gives a seat to a bot, either the seat of `color` or the first free seat. Starts the game
+    /// if every seat is taken
+    pub async fn add_bot(&mut self, color: Option<PieceColor>, limits: SearchLimits) -> Result<PieceColor, String> {
+        let color = match color {
+            Some(color) if !self.rules.borrow().colors.contains(&color) =>
+                return Err(String::from("The game has no such color")),
+            Some(color) if !self.is_seat_free(color) => return Err(String::from("Seat is already taken")),
+            Some(color) => color,
+            None => self.free_seat().ok_or_else(|| String::from("There are no free seats"))?,
+        };
+        info!("Bot took seat {}", color as u8);
+        self.bots.insert(color, limits);
+        self.send_msg(PlayerMessage::all_players(SocketData::BotJoined(color as u8))).await;
+        if !self.game_started {
+            self.start_if_ready().await;
+        }
+        Ok(color)
+    }
+
+    /// Checks if a bot has to make the next move
+    pub(super) fn bot_to_move(&self) -> bool {
+        self.game_started
+            && self.result == GameResult::Ongoing
+            && matches!(self.current_player, Some(color) if self.bots.contains_key(&color))
+    }
+
+    /// Lowers the limits of every bot to at most `max`
+    pub fn cap_bots(&mut self, max: SearchLimits) {
+        for limits in self.bots.values_mut() {
+            *limits = limits.capped(max);
+        }
+    }
+
+    /// Starts searching for a move for the bot to move. The search runs on a blocking thread, so
+    /// other games and clients aren't held up while the bot thinks
+    pub(super) fn start_bot(&mut self) {
+        let turn = self.current_player.unwrap();
+        let limits = self.bots[&turn];
+        let moves = self.position_moves().legal.clone();
+        let roots = self.root_moves(moves);
+        let pieces = self.rules.borrow().pieces.clone();
+        let stalemate = self.rules.borrow().stalemate;
+        let colors = self.active_colors();
+        let (sender, receiver) = oneshot::channel();
+        task::spawn_blocking(move || {
+            let best = search::best_move(roots, &pieces, &colors, turn, limits, stalemate);
+            // The game might have been closed while the bot was thinking
+            let _ = sender.send(best);
+        });
+        self.thinking = Some(BotSearch {
+            color: turn,
+            moves_made: self.history.borrow().len(),
+            receiver,
+            poll_time: Instant::now() + BOT_POLL_INTERVAL,
+        });
+    }
+
+    /// Makes each move and runs the rhai callbacks it triggers, so the search starts from the
+    /// positions the game would reach. The board, history and winner are restored afterwards
+    fn root_moves(&mut self, moves: Vec<GameMove>) -> Vec<RootMove> {
+        let board = self.board.borrow().clone();
+        let winner = *self.winner.borrow();
+        let mut roots = Vec::with_capacity(moves.len());
+        for mov in moves {
+            let symbol = match &board.board[mov.from.1][mov.from.0] {
+                Some(piece) => piece.symbol.clone(),
+                None => continue,
+            };
+            let piece = self.rules.borrow().pieces[&symbol].clone();
+            let result = self.board.borrow_mut().do_move(&mov);
+            if let Ok(take) = result {
+                self.history.borrow_mut().push(mov.clone());
+                if let Err(e) = rules::run_callbacks(&self.engine, &self.ast, &mut self.scope, &symbol, &piece, &mov, take) {
+                    debug!("Bot search: {}", e);
+                }
+                self.history.borrow_mut().pop();
+                roots.push(RootMove {
+                    board: self.board.borrow().clone(),
+                    winner: *self.winner.borrow(),
+                    mov,
+                });
+            }
+            *self.board.borrow_mut() = board.clone();
+            *self.winner.borrow_mut() = winner;
+        }
+        roots
+    }
+
+    /// Makes the move found by a bot once its search has finished. The move is dropped if the game
+    /// has gone on since the search started
+    pub(super) async fn finish_bot(&mut self) {
+        let search = match &mut self.thinking {
+            Some(search) => search,
+            None => return,
+        };
+        let best = match search.receiver.try_recv() {
+            Ok(best) => best,
+            Err(TryRecvError::Empty) => {
+                search.poll_time = Instant::now() + BOT_POLL_INTERVAL;
+                return;
+            },
+            Err(TryRecvError::Closed) => {
+                error!("Search of bot {:?} stopped without a result", search.color);
+                None
+            },
+        };
+        let search = self.thinking.take().unwrap();
+        if !self.bot_to_move()
+            || self.current_player != Some(search.color)
+            || self.history.borrow().len() != search.moves_made {
+            return;
+        }
+        let gamemove = match best {
+            Some(gamemove) => gamemove,
+            None => {
+                error!("Bot {:?} has no moves", search.color);
+                return;
+            },
+        };
+        let msg = match self.make_move(gamemove) {
+            Ok(msg) => msg,
+            Err(e) => {
+                error!("{}", e);
+                PlayerMessage::all_players(SocketData::Error(e.to_string()))
+            },
+        };
+        self.send_msg(msg).await;
+    }
+}
@@ -0,0 +1,78 @@
+use super::{Game, PlayerMessage};
+use crate::logic::{
+    color::PieceColor,
+    result::{GameResult, ResultReason},
+};
+use std::time::Instant;
+
+impl Game<'_> {
+
+    /// Starts the clock of the player to move
+    pub(super) fn start_clock(&mut self, color: PieceColor, now: Instant) {
+        if let Some(clock) = &mut self.clock {
+            clock.start_turn(color, now);
+        }
+    }
+
+    /// Stops the clock of the player that moved. Returns false if the player ran out of time
+    pub(super) fn stop_clock(&mut self, now: Instant) -> bool {
+        match &mut self.clock {
+            Some(clock) => clock.end_turn(now),
+            None => true,
+        }
+    }
+
+    /// Gets the time left for each player in milliseconds, in the order the players take turns
+    pub(super) fn clock_times(&self) -> Option<Vec<u64>> {
+        let now = Instant::now();
+        self.clock.as_ref().map(|clock| {
+            self.rules.borrow().colors.iter()
+                .map(|color| clock.remaining(*color, now).as_millis() as u64)
+                .collect()
+        })
+    }
+
+    /// Gets the time when the game has to be updated, which is when the player to move runs out
+    /// of time, the seat of a disconnected player is released or a bot has to move
+    pub fn deadline(&self) -> Option<Instant> {
+        let clock = match self.result {
+            GameResult::Ongoing => self.clock.as_ref().and_then(|clock| clock.deadline()),
+            _ => None,
+        };
+        let bot = match &self.thinking {
+            Some(search) => Some(search.poll_time),
+            None if self.bot_to_move() => Some(Instant::now()),
+            None => None,
+        };
+        clock.into_iter().chain(bot).chain(self.reserved.values().copied()).min()
+    }
+
+    /// Ends the game if the player to move has run out of time or a disconnected player hasn't
+    /// rejoined in time. Starts the search of a bot if it is its turn, and makes its move once the
+    /// search has finished
+    pub async fn update(&mut self, now: Instant) {
+        if let Some(msg) = self.release_seats(now) {
+            self.send_msg(msg).await;
+        }
+        match self.clock.as_ref().and_then(|clock| clock.deadline()) {
+            Some(deadline) if deadline <= now && self.result == GameResult::Ongoing => {
+                let loser = self.current_player.unwrap();
+                info!("{:?} ran out of time", loser);
+                self.stop_clock(now);
+                let msg = self.timeout(loser);
+                self.send_msg(msg).await;
+            },
+            _ => (),
+        }
+        if self.thinking.is_some() {
+            self.finish_bot().await;
+        } else if self.bot_to_move() {
+            self.start_bot();
+        }
+    }
+
+    /// Makes the player that ran out of time lose
+    pub(super) fn timeout(&mut self, loser: PieceColor) -> PlayerMessage {
+        self.lose(loser, ResultReason::Timeout)
+    }
+}
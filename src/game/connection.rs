@@ -1,58 +1,167 @@
-use super::Game;
-use std::net::SocketAddr;
+use super::{Game, PlayerMessage, SelectedPlayers};
+use crate::logic::{
+    color::PieceColor,
+    result::{GameResult, ResultReason},
+};
+use std::{
+    net::SocketAddr,
+    time::Instant,
+};
+use rand::{Rng, distributions::Alphanumeric};
+
+/// Length of the session tokens given to players
+const TOKEN_LENGTH: usize = 32;
 
 impl Game<'_> {
 
     /// Assigns a playertype to the newly connected client and sends initial data to the client. The
     /// procedure will also start the game if enough players are connected
     pub async fn new_connection(&mut self, addr: &SocketAddr) {
-        info!("Connection established with {}", addr);
         // Finding available color
-        let colors = self.rules.borrow().colors.clone();
-        let new_color = colors.iter().find(|color| 
-                self.players.iter().find(|(_, value)| Some(*color)==value.as_ref()).is_none());
+        let new_color = self.free_seat();
         debug!("New color is: {:?}", new_color);
         // Sending initial data to client
-        if let Ok(mut x) = self.clients.lock() {
-            let client = x.get_mut(&addr).unwrap();
-            match new_color {
-                Some(color) => client.new_player(
-                    *color,
-                    &self.rules.borrow().pieces,
-                    &self.rules.borrow().name).await,
-                None => client.new_spectator(
-                    &self.rules.borrow().pieces,
-                    &self.rules.borrow().name).await,
-            }
-        }
+        let token = new_color.map(|color| {
+            let token: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(TOKEN_LENGTH)
+                .map(char::from)
+                .collect();
+            self.seats.insert(token.clone(), color);
+            token
+        });
+        self.send_new_client(addr, new_color, token).await;
         // Mapping address to color
-        self.players.insert(*addr, new_color.map_or_else(|| None, |x| Some(*x)));
+        self.players.insert(*addr, new_color);
         debug!("{} clients connected", self.players.len());
-        // Starting game if enough players are connected and the game hasn't started yet
-        if !self.game_started && self.players.len() == self.rules.borrow().colors.len() {
-            info!("Starting game");
-            self.game_started = true;
-            self.current_player = Some(self.rules.borrow().colors[0]);
-            let msg = self.create_move(self.current_player.unwrap());
-            self.send_msg(msg).await;
+        self.send_game_state(addr).await;
+    }
+
+    /// Gives a reconnecting player their seat back. Returns false if the token doesn't belong to a
+    /// seat in this game
+    pub async fn rejoin(&mut self, addr: &SocketAddr, token: &str) -> bool {
+        let color = match self.seats.get(token) {
+            Some(color) => *color,
+            None => return false,
+        };
+        info!("Player {} rejoined", color as u8);
+        self.reserved.remove(&color);
+        // A connection that hasn't been closed yet loses the seat to the new connection
+        for value in self.players.values_mut() {
+            if *value == Some(color) {
+                *value = None;
+            }
+        }
+        self.send_new_client(addr, Some(color), Some(token.to_string())).await;
+        self.players.insert(*addr, Some(color));
+        self.send_game_state(addr).await;
+        true
+    }
+
+    /// Checks if no player or bot has taken a seat. Seats of disconnected players are kept for
+    /// them
+    pub(super) fn is_seat_free(&self, color: PieceColor) -> bool {
+        !self.players.values().any(|value| Some(color) == *value)
+            && !self.reserved.contains_key(&color)
+            && !self.bots.contains_key(&color)
+    }
+
+    /// Finds the first free seat
+    pub(super) fn free_seat(&self) -> Option<PieceColor> {
+        let colors = self.rules.borrow().colors.clone();
+        colors.into_iter().find(|color| self.is_seat_free(*color))
+    }
+
+    /// Checks if a session token belongs to a seat in this game
+    pub fn has_seat(&self, token: &str) -> bool {
+        self.seats.contains_key(token)
+    }
+
+    /// Sends the initial message to a client
+    async fn send_new_client(&self, addr: &SocketAddr, color: Option<PieceColor>, token: Option<String>) {
+        let (pieces, name) = {
+            let rules = self.rules.borrow();
+            (rules.pieces.clone(), rules.name.clone())
+        };
+        // The client might have disconnected before the lobby got to its message
+        let mut clients = self.clients.lock().await;
+        let client = match clients.get_mut(addr) {
+            Some(client) => client,
+            None => return,
+        };
+        match color {
+            Some(color) => client.new_player(color, &pieces, &name, token).await,
+            None => client.new_spectator(&pieces, &name).await,
+        }
+    }
+
+    /// Starts the game if every seat is taken, or sends the state of the game to a client that
+    /// joins after the game has started
+    async fn send_game_state(&mut self, addr: &SocketAddr) {
+        if !self.game_started {
+            self.start_if_ready().await;
         // Sending board state to new client if the game is already started
-        } else if self.game_started {
-            self.send_msg(self.create_move(self.current_player.unwrap())).await;
+        } else {
+            let msg = match self.result {
+                GameResult::Ongoing => PlayerMessage {
+                    players: SelectedPlayers::List(vec![*addr]),
+                    ..self.create_move()
+                },
+                _ => PlayerMessage::single_player(*addr, self.result_message()),
+            };
+            self.send_msg(msg).await;
+        }
+    }
+
+    /// Starts the game if every seat is taken by a player or a bot
+    pub(super) async fn start_if_ready(&mut self) {
+        let seated = self.players.values().filter(|color| color.is_some()).count() + self.bots.len();
+        if seated < self.rules.borrow().colors.len() {
+            return;
         }
+        info!("Starting game");
+        self.game_started = true;
+        self.current_player = Some(self.rules.borrow().colors[0]);
+        self.position_moves.take();
+        self.record_position();
+        self.record_start();
+        self.start_clock(self.current_player.unwrap(), Instant::now());
+        let msg = self.create_move();
+        self.send_msg(msg).await;
     }
 
-    /// Removes client from player list when connection is closed
+    /// Removes client from player list when connection is closed or the client leaves the game.
+    /// The seat of a player is kept for the grace period so the player can rejoin
     pub fn closed_connection(&mut self, addr: &SocketAddr) {
         if let Some(color) = self.players.get(addr).unwrap() {
             info!("Player {} disconnected", *color as u8);
+            if self.result == GameResult::Ongoing {
+                self.reserved.insert(*color, Instant::now() + self.grace_period);
+            }
         } else {
             info!("Spectator disconnected");
         }
         self.players.remove(addr);
-        if let Ok(mut x) = self.clients.lock() {
-            x.remove(addr);
-        }
         debug!("{} clients connected", self.players.len());
     }
 
+    /// Frees the seats of players that haven't rejoined within the grace period. A player that
+    /// leaves a running game forfeits it
+    pub(super) fn release_seats(&mut self, now: Instant) -> Option<PlayerMessage> {
+        let expired: Vec<PieceColor> = self.reserved.iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(color, _)| *color)
+            .collect();
+        let mut msg = None;
+        for color in expired {
+            info!("Seat of player {} was released", color as u8);
+            self.reserved.remove(&color);
+            self.seats.retain(|_, seat| *seat != color);
+            if self.game_started && self.result == GameResult::Ongoing {
+                msg = Some(self.lose(color, ResultReason::Abandonment));
+            }
+        }
+        msg
+    }
+
 }
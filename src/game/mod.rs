@@ -2,6 +2,21 @@
 mod connection;
 /// Validates user actions
 mod validation;
+/// Detects the end of the game
+mod result;
+/// Handles resignations and draw offers
+mod actions;
+/// Runs the chess clock
+mod clock;
+/// Saves and restores games
+mod save;
+/// Keeps a record of the moves in the game
+mod record;
+/// Plays moves for bots
+mod bot;
+/// Tests for games
+#[cfg(test)]
+mod test;
 
 use crate::{
     Error,
@@ -9,24 +24,29 @@ use crate::{
     networking::{
         self,
         receiveddata::ReceivedData,
-        socketdata::SocketData,
+        socketdata::{SocketData, GameInfo},
         ClientList,
     },
     logic::{
         board::GameBoard,
         color::PieceColor,
-        piece::GamePiece,
-        move_gen::{GameMove, generate_moves, is_checked},
+        move_gen::{self, GameMove},
+        result::GameResult,
+        clock::Clock,
+        search::SearchLimits,
     },
 };
 use std::{
     net::SocketAddr,
     collections::HashMap,
     rc::Rc,
-    cell::RefCell,
+    cell::{Ref, RefCell},
+    time::{Duration, Instant},
 };
 use rhai::{Engine, AST, Scope};
 
+pub use save::SavedGame;
+
 /// Type for mapping client addresses to player colors
 type PlayerList = HashMap<SocketAddr, Option<PieceColor>>;
 
@@ -38,10 +58,53 @@ pub struct Game<'a> {
     game_started: bool,
     current_player: Option<PieceColor>,
     rules: SharedRules,
+    /// Path to the rules file the game was created from
+    rules_path: String,
     engine: Engine,
     ast: AST,
     scope: Scope<'a>,
     winner: rules::WinnerState,
+    history: rules::MoveHistory,
+    result: GameResult,
+    /// Number of times each position has occured
+    positions: HashMap<String, usize>,
+    /// Number of moves since the last capture or move of a piece that resets the move limit
+    quiet_moves: usize,
+    /// Players that have offered or accepted a draw
+    draw_offers: Vec<PieceColor>,
+    /// Time left for each player, if the game uses a time control
+    clock: Option<Clock>,
+    /// Session tokens of the players mapped to their colors
+    seats: HashMap<String, PieceColor>,
+    /// Seats of disconnected players and when they are released
+    reserved: HashMap<PieceColor, Instant>,
+    /// How long the seat of a disconnected player is kept
+    grace_period: Duration,
+    /// Notation of the moves made in the game
+    record: Vec<String>,
+    /// Seconds since the unix epoch when the game started
+    start_time: Option<u64>,
+    /// Seats taken by bots and how they search for moves
+    bots: HashMap<PieceColor, SearchLimits>,
+    /// Search of the bot to move, if it is thinking
+    thinking: Option<bot::BotSearch>,
+    /// Players that have lost while the game goes on
+    eliminated: Vec<PieceColor>,
+    /// Errors of failing rhai callbacks, which are sent to the players with the next message
+    script_errors: RefCell<Vec<Error>>,
+    /// Moves of the current position, once they have been generated
+    position_moves: RefCell<Option<PositionMoves>>,
+}
+
+/// Moves and attacks in a position, which are generated once and used to find the result,
+/// validate moves and send the position to the players
+struct PositionMoves {
+    /// Legal moves of the player to move
+    legal: Vec<GameMove>,
+    /// Squares attacked by each player, in turn order
+    attacked: Vec<Vec<(usize, usize)>>,
+    /// Whether a royal piece of the player to move is attacked
+    in_check: bool,
 }
 
 /// Stores which players a message should be sent to
@@ -59,9 +122,11 @@ struct PlayerMessage {
 impl<'a> Game<'a> {
 
     /// Creates a new game
-    pub fn new(clients: ClientList, env: RulesEnv<'a>) -> Game<'a> {
+    pub fn new(clients: ClientList, env: RulesEnv<'a>, grace_period: Duration) -> Game<'a> {
         info!("Game: {}", env.rules.borrow().name);
         let board = env.rules.borrow().board.clone();
+        let clock = env.rules.borrow().time_control
+            .map(|control| Clock::new(control, &env.rules.borrow().colors));
         Self {
             clients,
             board,
@@ -69,10 +134,27 @@ impl<'a> Game<'a> {
             game_started: false,
             current_player: None,
             rules: env.rules,
+            rules_path: env.path,
             engine: env.engine,
             ast: env.ast,
             scope: env.scope,
             winner: env.winner,
+            history: env.history,
+            result: GameResult::Ongoing,
+            positions: HashMap::new(),
+            quiet_moves: 0,
+            draw_offers: vec![],
+            clock,
+            seats: HashMap::new(),
+            reserved: HashMap::new(),
+            grace_period,
+            record: vec![],
+            start_time: None,
+            bots: HashMap::new(),
+            thinking: None,
+            eliminated: vec![],
+            script_errors: RefCell::new(vec![]),
+            position_moves: RefCell::new(None),
         }
     }
 
@@ -92,48 +174,93 @@ impl<'a> Game<'a> {
     fn evaluate_message(&mut self, content: &ReceivedData, addr: &SocketAddr) -> Result<PlayerMessage, Error> {
         match content {
             ReceivedData::Move(_) => self.evaluate_move(content, addr),
+            ReceivedData::Resign => self.evaluate_resign(addr),
+            ReceivedData::OfferDraw => self.evaluate_offer_draw(addr),
+            ReceivedData::AcceptDraw => self.evaluate_accept_draw(addr),
+            ReceivedData::DeclineDraw => self.evaluate_decline_draw(addr),
+            _ => Ok(PlayerMessage::single_player(*addr, SocketData::InvalidMessage(
+                        String::from("Unknown action")))),
         }
     }
 
     /// Evaluates a move from a player
     fn evaluate_move(&mut self, content: &ReceivedData, addr: &SocketAddr) -> Result<PlayerMessage, Error> {
         // Validates message
-        if let Err(err_msg) = self.validate_message(content, addr) {
-            return Ok(PlayerMessage::single_player(*addr, SocketData::InvalidMessage(err_msg)));
-        }
+        let gamemove = match self.validate_message(content, addr) {
+            Ok(gamemove) => gamemove,
+            Err(err_msg) => return Ok(PlayerMessage::single_player(*addr, SocketData::InvalidMessage(err_msg))),
+        };
         debug!("Message validated");
-        let ReceivedData::Move(gamemove) = content;
+        self.make_move(gamemove)
+    }
+
+    /// Makes a validated move for the player to move, and returns the new state of the game
+    fn make_move(&mut self, gamemove: GameMove) -> Result<PlayerMessage, Error> {
+        let now = Instant::now();
+        if !self.stop_clock(now) {
+            return Ok(self.timeout(self.current_player.unwrap()));
+        }
         info!("{:?} made move from {:?} to {:?}", self.current_player.unwrap(), gamemove.from, gamemove.to);
-        self.do_move(gamemove)?;
-        let colors = &self.rules.borrow().colors;
-        let next_player = colors[(self.current_player.unwrap() as usize + 1) % colors.len()];
-        self.current_player = Some(next_player);
-        // Checking for winners
-        if let Some(winner) = self.find_winner().or(*self.winner.borrow())  {
-            info!("Winner: {:?}", winner);
-            return Ok(PlayerMessage::all_players(SocketData::winner(winner, &self.board.borrow())));
+        let notation = self.move_notation(&gamemove);
+        self.do_move(&gamemove)?;
+        // Making a move declines draw offers from other players
+        if !self.draw_offers.contains(&self.current_player.unwrap()) {
+            self.draw_offers.clear();
         }
-        return Ok(self.create_move(next_player));
+        let next_player = self.next_player(self.current_player.unwrap());
+        self.current_player = Some(next_player);
+        self.record_position();
+        // Checking if the game has ended
+        let result = self.find_result();
+        self.record_move(notation, &result);
+        Ok(self.apply_result(result, now))
     }
 
-    /// Sends message to specied players
+    /// Sends message to specied players, after the errors of rhai callbacks that failed since the
+    /// last message
     async fn send_msg(&mut self, msg: PlayerMessage) {
-        if let Ok(mut x) = self.clients.lock() {
-            match msg.players {
-                SelectedPlayers::All => {
-                    for i in x.values_mut() {
-                        i.send_socket(&msg.message).await;
-                    }
-                },
-                SelectedPlayers::List(players) => {
-                    for player in players {
-                        x.get_mut(&player).unwrap().send_socket(&msg.message).await;
-                    }
-                }
+        let mut errors: Vec<String> = vec![];
+        for error in self.script_errors.borrow_mut().drain(..) {
+            // Callbacks of pieces of the same kind fail the same way, so errors repeat
+            if !errors.contains(&error.to_string()) {
+                errors.push(error.to_string());
+            }
+        }
+        for error in errors {
+            self.send_to_players(&SelectedPlayers::All, &SocketData::Error(error)).await;
+        }
+        self.send_to_players(&msg.players, &msg.message).await;
+    }
+
+    /// Sends data to the selected players
+    async fn send_to_players(&self, players: &SelectedPlayers, data: &SocketData) {
+        let players = match players {
+            SelectedPlayers::All => self.players.keys().copied().collect(),
+            SelectedPlayers::List(players) => players.clone(),
+        };
+        let mut clients = self.clients.lock().await;
+        for player in players {
+            if let Some(client) = clients.get_mut(&player) {
+                client.send_socket(data).await;
             }
         }
     }
 
+    /// Creates a summary of the game for the game list
+    pub fn info(&self, name: &str) -> GameInfo {
+        networking::socketdata::game_info(
+            name,
+            &self.rules.borrow().name,
+            self.players.values().filter(|color| color.is_some()).count() + self.bots.len(),
+            self.rules.borrow().colors.len(),
+            self.game_started)
+    }
+
+    /// Checks if no clients have joined the game and no seats are kept for disconnected players
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty() && self.reserved.is_empty()
+    }
+
     /// Makes move and runs rhai functions
     fn do_move(&mut self, gamemove: &GameMove) -> Result<(), Error> {
         // Finds piece that is moved
@@ -141,68 +268,55 @@ impl<'a> Game<'a> {
         let move_piece_symbol = &self.board.borrow().board[from.1][from.0].as_ref().unwrap().symbol.clone();
         let move_piece = &self.rules.borrow().pieces[move_piece_symbol];
         // Does move
-        let take = self.board.borrow_mut().do_move(&gamemove)?;
-        // Runs events
-        if let Some(after_move) = &move_piece.after_move {
-            let _: () = self.engine.call_fn(&mut self.scope,
-                                            &self.ast,
-                                            &after_move,
-                                            (gamemove.clone(), )).unwrap();
+        let take = self.board.borrow_mut().do_move(gamemove)?;
+        self.history.borrow_mut().push(gamemove.clone());
+        // Captures and moves of pieces like pawns reset the move limit
+        if take || move_piece.resets_move_limit {
+            self.quiet_moves = 0;
+        } else {
+            self.quiet_moves += 1;
         }
-        if take {
-            if let Some(after_take) = &move_piece.after_take {
-                let _: () = self.engine.call_fn(&mut self.scope,
-                                                &self.ast,
-                                                &after_take,
-                                                (gamemove.clone(), )).unwrap();
-            }
+        // Runs events. The move stands if a callback fails
+        if let Err(e) = rules::run_callbacks(&self.engine, &self.ast, &mut self.scope, move_piece_symbol, move_piece, gamemove, take) {
+            error!("{}", e);
+            self.script_errors.borrow_mut().push(e);
         }
+        self.position_moves.take();
         Ok(())
     }
 
-    /// Finds a winner if it exists
-    fn find_winner(&self) -> Option<PieceColor> {
-        let king_pieces = self.board.borrow().get_positions_of(&|piece: &GamePiece| {
-            piece.color == self.current_player.unwrap() && self.rules.borrow().pieces[&piece.symbol].kingstatus
-        });
-        let opp_color = self.rules.borrow().colors[((self.current_player? as usize + 1) % self.rules.borrow().colors.len()) as usize];
-        for (x,y) in king_pieces {
-            if is_checked(self.current_player?, &x, &y, &self.rules.borrow().pieces, &self.board.borrow()) {
-                debug!("King, {:?}, is checked", (x,y));
-                let valid_moves: Vec<GameMove> = generate_moves(self.current_player?, &self.rules.borrow().pieces, &self.board.borrow(), Some((&self.ast, &self.engine)))
-                    .into_iter().filter(|mov| self.king_checked_predicate(mov)).collect();
-                if valid_moves.len() > 0 {
-                    debug!("King can be saved: {:?}", valid_moves);
-                    return None;
-                }
-                debug!("King has no legal moves!");
-                return Some(opp_color);
-            }
+    /// Gets the moves of the current position, generating them the first time they are needed.
+    /// They are cleared whenever the board or the player to move changes
+    fn position_moves(&self) -> Ref<'_, PositionMoves> {
+        if self.position_moves.borrow().is_none() {
+            let turn = self.current_player.unwrap();
+            let rules = self.rules.borrow();
+            let board = self.board.borrow();
+            let env = move_gen::RhaiEnv { ast: &self.ast, engine: &self.engine, errors: Some(&self.script_errors) };
+            let moves = PositionMoves {
+                legal: move_gen::legal_moves(turn, &rules.pieces, &board, Some(env)),
+                attacked: rules.colors.iter()
+                    .map(|color| move_gen::attacked_squares(*color, &rules.pieces, &board))
+                    .collect(),
+                in_check: move_gen::in_check(turn, &rules.pieces, &board),
+            };
+            *self.position_moves.borrow_mut() = Some(moves);
         }
-        return None;
-    }
-
-    /// Creates move data with board state
-    fn create_move(&self, turn: PieceColor) -> PlayerMessage {
-        let valid_moves = generate_moves(turn, &self.rules.borrow().pieces, &self.board.borrow(), Some((&self.ast, &self.engine)))
-            .into_iter().filter(|mov| self.king_checked_predicate(mov)).collect();
-        let data = networking::socketdata::create_move(turn, &self.board.borrow(), valid_moves);
-        return PlayerMessage::all_players(data);
+        Ref::map(self.position_moves.borrow(), |moves| moves.as_ref().unwrap())
     }
 
-    /// Predicate used to check if king is checked after move
-    fn king_checked_predicate(&self, mov: &GameMove) -> bool {
-        let mut nboard = self.board.borrow().clone();
-        nboard.do_move(&mov);
-        let king_pieces = nboard.get_positions_of(&|piece: &GamePiece| {
-            piece.color == self.current_player.unwrap() && self.rules.borrow().pieces[&piece.symbol].kingstatus
-        });
-        for (kx, ky) in &king_pieces {
-            if is_checked(self.current_player.unwrap(), kx, ky, &self.rules.borrow().pieces, &nboard) {
-                return false;
-            }
-        }
-        return true;
+    /// Creates move data with board state for the player to move
+    fn create_move(&self) -> PlayerMessage {
+        let moves = self.position_moves();
+        let rules = self.rules.borrow();
+        let board = self.board.borrow();
+        let material = rules.colors.iter()
+            .map(|color| board.material(*color, &rules.pieces))
+            .collect();
+        let eliminated = self.eliminated.iter().map(|color| *color as u8).collect();
+        let data = networking::socketdata::create_move(self.current_player.unwrap(), &board, moves.legal.clone(),
+            self.clock_times(), material, moves.attacked.clone(), eliminated);
+        PlayerMessage::all_players(data)
     }
 }
 
@@ -0,0 +1,67 @@
+use super::Game;
+use crate::logic::{
+    move_gen::GameMove,
+    result::{GameResult, ResultReason},
+    record::{self, GameRecord},
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+impl Game<'_> {
+
+    /// Writes the notation of a move before it is made
+    pub(super) fn move_notation(&self, gamemove: &GameMove) -> String {
+        record::move_notation(gamemove, &self.board.borrow(), &self.rules.borrow().pieces)
+    }
+
+    /// Adds a move to the record, marking checks and checkmates. Has to be called after the move
+    /// is made and the result of the game is found
+    pub(super) fn record_move(&mut self, mut notation: String, result: &GameResult) {
+        if let GameResult::Win(_, ResultReason::Checkmate) | GameResult::Eliminated(_, ResultReason::Checkmate) = result {
+            notation.push('#');
+        } else if self.position_moves().in_check {
+            notation.push('+');
+        }
+        self.record.push(notation);
+    }
+
+    /// Remembers when the game started for the record
+    pub(super) fn record_start(&mut self) {
+        self.start_time = Some(SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs()));
+    }
+
+    /// Seconds since the unix epoch when the game started
+    pub fn start_time(&self) -> Option<u64> {
+        self.start_time
+    }
+
+    /// Creates the record of the game
+    pub fn record(&self, name: &str) -> GameRecord {
+        let rules = self.rules.borrow();
+        let board = self.board.borrow();
+        let mut tags = vec![
+            (String::from("Event"), name.to_string()),
+            (String::from("Date"), self.start_time.map_or_else(|| String::from("????.??.??"), record::date_notation)),
+            (String::from("Variant"), rules.name.clone()),
+            (String::from("Board"), format!("{}x{}", board.width, board.height)),
+        ];
+        for (i, color) in rules.colors.iter().enumerate() {
+            tags.push((format!("{:?}", color), format!("Player {}", i + 1)));
+        }
+        if let Some(clock) = &self.clock {
+            tags.push((String::from("TimeControl"), clock.control.to_string()));
+        }
+        let result = record::result_notation(&self.result, &rules.colors, &board.teams);
+        tags.push((String::from("Result"), result.clone()));
+        if let GameResult::Win(_, reason) | GameResult::Draw(reason) = self.result {
+            tags.push((String::from("Termination"), format!("{:?}", reason)));
+        }
+        GameRecord {
+            tags,
+            moves: self.record.clone(),
+            players: rules.colors.len(),
+            result,
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use super::{Game, PlayerMessage};
+use crate::{
+    networking::socketdata::SocketData,
+    logic::{
+        color::PieceColor,
+        result::{self, GameResult, ResultReason, StalemateRule},
+    },
+};
+use std::time::Instant;
+
+impl Game<'_> {
+
+    /// Finds the result of the game after a move, from the perspective of the player to move
+    pub(super) fn find_result(&self) -> GameResult {
+        let turn = self.current_player.unwrap();
+        if let Some(winner) = *self.winner.borrow() {
+            return GameResult::Win(winner, ResultReason::Rules);
+        }
+        let rules = self.rules.borrow();
+        let moves = self.position_moves();
+        if moves.legal.is_empty() {
+            if moves.in_check {
+                debug!("King has no legal moves!");
+                return self.loss(turn, ResultReason::Checkmate);
+            }
+            return match rules.stalemate {
+                StalemateRule::Draw => GameResult::Draw(ResultReason::Stalemate),
+                StalemateRule::Loss => self.loss(turn, ResultReason::Stalemate),
+                StalemateRule::Win => GameResult::Win(turn, ResultReason::Stalemate),
+            };
+        }
+        let key = result::position_key(&self.board.borrow(), &rules.pieces, turn);
+        if rules.repetitions > 0 && self.positions.get(&key).copied().unwrap_or(0) >= rules.repetitions {
+            return GameResult::Draw(ResultReason::Repetition);
+        }
+        if rules.move_limit > 0 && self.quiet_moves >= rules.move_limit * self.active_colors().len() {
+            return GameResult::Draw(ResultReason::MoveLimit);
+        }
+        if rules.insufficient_material && result::insufficient_material(&self.board.borrow(), &rules.pieces) {
+            return GameResult::Draw(ResultReason::InsufficientMaterial);
+        }
+        GameResult::Ongoing
+    }
+
+    /// Counts the occurences of the current position
+    pub(super) fn record_position(&mut self) {
+        let key = result::position_key(&self.board.borrow(), &self.rules.borrow().pieces, self.current_player.unwrap());
+        *self.positions.entry(key).or_insert(0) += 1;
+    }
+
+    /// Finds the result of a player losing. The game ends when only one other player or team is
+    /// left, otherwise the player is eliminated. If no other player is left, nobody wins and the
+    /// game is drawn
+    pub(super) fn loss(&self, loser: PieceColor, reason: ResultReason) -> GameResult {
+        let teams = self.board.borrow().teams;
+        let remaining: Vec<PieceColor> = self.active_colors().into_iter().filter(|color| *color != loser).collect();
+        match remaining.first() {
+            Some(first) if remaining.iter().any(|color| !teams.allied(*color, *first)) => GameResult::Eliminated(loser, reason),
+            Some(first) => GameResult::Win(*first, reason),
+            None => GameResult::Draw(reason),
+        }
+    }
+
+    /// Makes a player lose the game and creates the message announcing the new state of the game
+    pub(super) fn lose(&mut self, loser: PieceColor, reason: ResultReason) -> PlayerMessage {
+        let result = self.loss(loser, reason);
+        self.apply_result(result, Instant::now())
+    }
+
+    /// Continues the game after its result is found. The player to move starts their turn, a
+    /// player that lost is eliminated or the game ends
+    pub(super) fn apply_result(&mut self, result: GameResult, now: Instant) -> PlayerMessage {
+        match result {
+            GameResult::Ongoing => {
+                let turn = self.current_player.unwrap();
+                self.start_clock(turn, now);
+                self.create_move()
+            },
+            GameResult::Eliminated(color, reason) => self.eliminate(color, reason, now),
+            result => self.end_game(result),
+        }
+    }
+
+    /// Removes a player from the game, taking their pieces off the board. The turn passes on if
+    /// it was theirs
+    fn eliminate(&mut self, color: PieceColor, reason: ResultReason, now: Instant) -> PlayerMessage {
+        info!("{:?} was eliminated: {:?}", color, reason);
+        self.eliminated.push(color);
+        self.draw_offers.retain(|c| *c != color);
+        for square in self.board.borrow_mut().board.iter_mut().flatten() {
+            if matches!(square, Some(piece) if piece.color == color) {
+                *square = None;
+            }
+        }
+        self.position_moves.take();
+        if self.current_player != Some(color) {
+            return self.create_move();
+        }
+        self.stop_clock(now);
+        self.current_player = Some(self.next_player(color));
+        self.position_moves.take();
+        let result = self.find_result();
+        self.apply_result(result, now)
+    }
+
+    /// Ends the game and creates the message announcing the result
+    pub(super) fn end_game(&mut self, result: GameResult) -> PlayerMessage {
+        info!("Game ended: {:?}", result);
+        if let Some(clock) = &mut self.clock {
+            clock.stop(std::time::Instant::now());
+        }
+        self.result = result;
+        PlayerMessage::all_players(self.result_message())
+    }
+
+    /// Creates the message announcing the result of the game
+    pub(super) fn result_message(&self) -> SocketData {
+        match self.result {
+            GameResult::Win(winner, reason) => {
+                let board = self.board.borrow();
+                let allies = self.rules.borrow().colors.iter()
+                    .copied()
+                    .filter(|color| *color != winner && board.teams.allied(*color, winner))
+                    .collect();
+                SocketData::winner(winner, allies, reason, &board)
+            },
+            GameResult::Draw(reason) => SocketData::draw(reason, &self.board.borrow()),
+            GameResult::Ongoing | GameResult::Eliminated(..) => unreachable!(),
+        }
+    }
+
+    /// Gets the player that plays after `color`, skipping eliminated players
+    pub(super) fn next_player(&self, color: PieceColor) -> PieceColor {
+        let colors = &self.rules.borrow().colors;
+        let index = colors.iter().position(|c| *c == color).unwrap_or(0);
+        (1..=colors.len())
+            .map(|i| colors[(index + i) % colors.len()])
+            .find(|c| !self.eliminated.contains(c))
+            .unwrap_or(color)
+    }
+
+    /// Gets the players that haven't been eliminated, in turn order
+    pub(super) fn active_colors(&self) -> Vec<PieceColor> {
+        self.rules.borrow().colors.iter()
+            .copied()
+            .filter(|color| !self.eliminated.contains(color))
+            .collect()
+    }
+}
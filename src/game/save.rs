@@ -0,0 +1,130 @@
+use super::Game;
+use crate::{
+    Error,
+    logic::{
+        board::GameBoard,
+        color::PieceColor,
+        move_gen::GameMove,
+        result::GameResult,
+        clock::{Clock, TimeControl},
+        search::SearchLimits,
+    },
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use serde::{Serialize, Deserialize};
+
+/// State of a game written to disk, from which the game can be restored
+#[derive(Serialize, Deserialize)]
+pub struct SavedGame {
+    /// Name of the game in the lobby
+    pub name: String,
+    /// Path to the rules file of the game
+    pub rules: String,
+    /// Board with the pieces and whether they have moved
+    board: GameBoard,
+    game_started: bool,
+    current_player: Option<PieceColor>,
+    /// Moves made in the game
+    history: Vec<GameMove>,
+    /// Winner set by the rules script
+    winner: Option<PieceColor>,
+    /// Variables in the scope of the rules script
+    variables: Vec<(String, serde_json::Value)>,
+    result: GameResult,
+    positions: HashMap<String, usize>,
+    quiet_moves: usize,
+    draw_offers: Vec<PieceColor>,
+    /// Time control of the game
+    time_control: Option<TimeControl>,
+    /// Milliseconds left for each player in turn order, if the game uses a time control
+    clocks: Option<Vec<u64>>,
+    /// Session tokens of the players, so they can rejoin the restored game
+    seats: HashMap<String, PieceColor>,
+    /// Notation of the moves made in the game
+    #[serde(default)]
+    record: Vec<String>,
+    /// Seconds since the unix epoch when the game started
+    #[serde(default)]
+    start_time: Option<u64>,
+    /// Seats taken by bots
+    #[serde(default)]
+    bots: Vec<(PieceColor, SearchLimits)>,
+    /// Players that have lost while the game goes on
+    #[serde(default)]
+    eliminated: Vec<PieceColor>,
+}
+
+impl Game<'_> {
+
+    /// Captures the state of the game
+    pub fn save(&self, name: &str) -> Result<SavedGame, Error> {
+        let variables = self.scope.iter()
+            .map(|(name, _, value)| Ok((name.to_string(), serde_json::to_value(value)?)))
+            .collect::<Result<_, Error>>()?;
+        Ok(SavedGame {
+            name: name.to_string(),
+            rules: self.rules_path.clone(),
+            board: self.board.borrow().clone(),
+            game_started: self.game_started,
+            current_player: self.current_player,
+            history: self.history.borrow().clone(),
+            winner: *self.winner.borrow(),
+            variables,
+            result: self.result.clone(),
+            positions: self.positions.clone(),
+            quiet_moves: self.quiet_moves,
+            draw_offers: self.draw_offers.clone(),
+            time_control: self.clock.as_ref().map(|clock| clock.control),
+            clocks: self.clock_times(),
+            seats: self.seats.clone(),
+            record: self.record.clone(),
+            start_time: self.start_time,
+            bots: self.bots.iter().map(|(color, limits)| (*color, *limits)).collect(),
+            eliminated: self.eliminated.clone(),
+        })
+    }
+
+    /// Restores the state of a saved game. The seats of the players are kept for them until they
+    /// rejoin or the grace period runs out
+    pub fn restore(&mut self, saved: SavedGame) -> Result<(), Error> {
+        // Teams come from the rules, not the save
+        let teams = self.board.borrow().teams;
+        *self.board.borrow_mut() = saved.board;
+        self.board.borrow_mut().teams = teams;
+        *self.history.borrow_mut() = saved.history;
+        *self.winner.borrow_mut() = saved.winner;
+        for (name, value) in saved.variables {
+            self.scope.push_dynamic(name, rhai::serde::to_dynamic(value)?);
+        }
+        self.game_started = saved.game_started;
+        self.current_player = saved.current_player;
+        self.position_moves.take();
+        self.result = saved.result;
+        self.positions = saved.positions;
+        self.quiet_moves = saved.quiet_moves;
+        self.draw_offers = saved.draw_offers;
+        self.seats = saved.seats;
+        self.record = saved.record;
+        self.start_time = saved.start_time;
+        self.bots = saved.bots.into_iter().collect();
+        self.eliminated = saved.eliminated;
+        let now = Instant::now();
+        self.clock = saved.time_control
+            .map(|control| Clock::new(control, &self.rules.borrow().colors));
+        if let (Some(clock), Some(times)) = (&mut self.clock, saved.clocks) {
+            for (color, time) in self.rules.borrow().colors.iter().zip(times) {
+                clock.set_remaining(*color, Duration::from_millis(time));
+            }
+        }
+        if self.game_started && self.result == GameResult::Ongoing {
+            for color in self.seats.values() {
+                self.reserved.insert(*color, now + self.grace_period);
+            }
+            self.start_clock(self.current_player.unwrap(), now);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use super::Game;
+use crate::{
+    args::ScriptLimits,
+    rules::RulesEnv,
+    networking::receiveddata::ReceivedData,
+    logic::{
+        color::PieceColor,
+        result::{GameResult, ResultReason},
+        move_gen::GameMove,
+        clock::Clock,
+    },
+};
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    collections::HashMap,
+    time::Duration,
+};
+use structopt::StructOpt;
+use tokio::sync::Mutex;
+
+/// Test that moves are only accepted from the player to move, and not from spectators
+#[tokio::test]
+async fn validate_moves() {
+    let (alice, bob, carol) = (helper_addr(1), helper_addr(2), helper_addr(3));
+    let mut game = helper_game("games/standard.rhai", &[alice, bob]).await;
+    game.new_connection(&carol).await;
+    let e4 = ReceivedData::Move(GameMove::new(4, 6, 4, 4));
+    assert_eq!(game.validate_message(&e4, &carol), Err(String::from("Spectators cannot move")));
+    assert_eq!(game.validate_message(&e4, &bob), Err(String::from("It is not you turn")));
+    assert_eq!(game.validate_message(&e4, &helper_addr(4)), Err(String::from("Client has not connected")));
+    let too_far = ReceivedData::Move(GameMove::new(4, 6, 4, 3));
+    assert_eq!(game.validate_message(&too_far, &alice), Err(String::from("Move is not valid")));
+    assert!(game.validate_message(&e4, &alice).is_ok());
+}
+
+/// Test that a player can resign, and that spectators and players of a finished game can't
+#[tokio::test]
+async fn resign() {
+    let (alice, bob, carol) = (helper_addr(1), helper_addr(2), helper_addr(3));
+    let mut game = helper_game("games/standard.rhai", &[alice, bob]).await;
+    game.new_connection(&carol).await;
+    game.handle_message(&ReceivedData::Resign, &carol).await;
+    assert_eq!(game.result, GameResult::Ongoing);
+    game.handle_message(&ReceivedData::Resign, &alice).await;
+    assert_eq!(game.result, GameResult::Win(PieceColor::Black, ResultReason::Resignation));
+    game.handle_message(&ReceivedData::Resign, &bob).await;
+    assert_eq!(game.result, GameResult::Win(PieceColor::Black, ResultReason::Resignation));
+}
+
+/// Test that a draw offer can be declined, and that the game is drawn once it is accepted
+#[tokio::test]
+async fn draw_offers() {
+    let (alice, bob) = (helper_addr(1), helper_addr(2));
+    let mut game = helper_game("games/standard.rhai", &[alice, bob]).await;
+    // Nothing can be accepted before a draw is offered
+    game.handle_message(&ReceivedData::AcceptDraw, &bob).await;
+    assert!(game.draw_offers.is_empty());
+
+    game.handle_message(&ReceivedData::OfferDraw, &alice).await;
+    assert_eq!(game.draw_offers, vec![PieceColor::White]);
+    // The player offering the draw can't accept it
+    game.handle_message(&ReceivedData::AcceptDraw, &alice).await;
+    assert_eq!(game.result, GameResult::Ongoing);
+    game.handle_message(&ReceivedData::DeclineDraw, &bob).await;
+    assert!(game.draw_offers.is_empty());
+    assert_eq!(game.result, GameResult::Ongoing);
+
+    game.handle_message(&ReceivedData::OfferDraw, &alice).await;
+    game.handle_message(&ReceivedData::AcceptDraw, &bob).await;
+    assert_eq!(game.result, GameResult::Draw(ResultReason::Agreement));
+}
+
+/// Test that a disconnected player keeps their seat and can take it back with their token
+#[tokio::test]
+async fn rejoin() {
+    let (alice, bob, carol, dave) = (helper_addr(1), helper_addr(2), helper_addr(3), helper_addr(4));
+    let mut game = helper_game("games/standard.rhai", &[alice, bob]).await;
+    let token = helper_token(&game, PieceColor::White);
+    game.closed_connection(&alice);
+    assert!(game.reserved.contains_key(&PieceColor::White));
+    // New clients can't take the seat while it is kept
+    game.new_connection(&carol).await;
+    assert_eq!(game.players[&carol], None);
+
+    assert!(!game.rejoin(&dave, "wrong token").await);
+    assert!(game.rejoin(&dave, &token).await);
+    assert_eq!(game.players[&dave], Some(PieceColor::White));
+    assert!(game.reserved.is_empty());
+    assert_eq!(game.result, GameResult::Ongoing);
+}
+
+/// Test that the seat of a player that doesn't rejoin is released after the grace period, and
+/// that the player forfeits the game
+#[tokio::test]
+async fn release_seat() {
+    let (alice, bob) = (helper_addr(1), helper_addr(2));
+    let mut game = helper_game("games/standard.rhai", &[alice, bob]).await;
+    let token = helper_token(&game, PieceColor::Black);
+    game.closed_connection(&bob);
+    let deadline = game.reserved[&PieceColor::Black];
+    assert_eq!(game.deadline(), Some(deadline));
+
+    game.update(deadline - Duration::from_secs(1)).await;
+    assert!(game.has_seat(&token));
+    game.update(deadline).await;
+    assert!(!game.has_seat(&token));
+    assert!(game.reserved.is_empty());
+    assert_eq!(game.result, GameResult::Win(PieceColor::White, ResultReason::Abandonment));
+}
+
+/// Test that a saved game resumes with the same board, history, turn and clocks, and that its
+/// players can rejoin it
+#[tokio::test]
+async fn save_and_resume() {
+    let (alice, bob) = (helper_addr(1), helper_addr(2));
+    let mut game = helper_game("games/standard.rhai", &[]).await;
+    game.clock = Some(Clock::new("300+2".parse().unwrap(), &[PieceColor::White, PieceColor::Black]));
+    game.new_connection(&alice).await;
+    game.new_connection(&bob).await;
+    game.handle_message(&ReceivedData::Move(GameMove::new(4, 6, 4, 4)), &alice).await;
+    game.handle_message(&ReceivedData::Move(GameMove::new(4, 1, 4, 3)), &bob).await;
+    let token = helper_token(&game, PieceColor::White);
+
+    let json = serde_json::to_string(&game.save("blitz").unwrap()).unwrap();
+    let mut resumed = helper_game("games/standard.rhai", &[]).await;
+    resumed.restore(serde_json::from_str(&json).unwrap()).unwrap();
+    assert_eq!(resumed.current_player, Some(PieceColor::White));
+    assert_eq!(*resumed.history.borrow(), *game.history.borrow());
+    assert_eq!(resumed.record, vec!["e2-e4", "e7-e5"]);
+    assert_eq!(resumed.positions, game.positions);
+    assert!(resumed.board.borrow().board[4][4].is_some() && resumed.board.borrow().board[6][4].is_none());
+    // Both players got the increment for their move, so the clocks weren't reset
+    let (times, resumed_times) = (game.clock_times().unwrap(), resumed.clock_times().unwrap());
+    for (time, resumed_time) in times.iter().zip(resumed_times.iter()) {
+        assert!(*resumed_time > 300_000 && (*time as i64 - *resumed_time as i64).abs() < 1000);
+    }
+
+    // The seats are kept until the players rejoin, and the game goes on
+    assert_eq!(resumed.reserved.len(), 2);
+    assert!(resumed.rejoin(&alice, &token).await);
+    resumed.handle_message(&ReceivedData::Move(GameMove::new(6, 7, 5, 5)), &alice).await;
+    assert_eq!(resumed.history.borrow().len(), 3);
+    assert_eq!(resumed.current_player, Some(PieceColor::Black));
+}
+
+/// Creates a game from a rules file and seats the given clients in turn order, which starts the
+/// game if every seat is taken
+async fn helper_game(rules: &str, players: &[SocketAddr]) -> Game<'static> {
+    let limits = ScriptLimits::from_iter(&["test"]);
+    let env = RulesEnv::new(rules, &limits).unwrap();
+    let mut game = Game::new(Arc::new(Mutex::new(HashMap::new())), env, Duration::from_secs(60));
+    for addr in players {
+        game.new_connection(addr).await;
+    }
+    game
+}
+
+/// Gets the session token of the seat of `color`
+fn helper_token(game: &Game, color: PieceColor) -> String {
+    game.seats.iter().find(|(_, seat)| **seat == color).unwrap().0.clone()
+}
+
+/// Creates the address of a client
+fn helper_addr(port: u16) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
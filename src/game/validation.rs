@@ -2,39 +2,63 @@ use super::{Game};
 use crate::{
     networking::{receiveddata::ReceivedData},
     logic::{
-        move_gen::generate_moves,
+        move_gen::GameMove,
+        result::GameResult,
+        color::PieceColor,
     }
 };
 use std::{net::SocketAddr};
 
 impl Game<'_> {
 
-    /// Validates a message from a client or returns error message
-    pub fn validate_message(&mut self, content: &ReceivedData, addr: &SocketAddr) -> Result<(), String> {
-        return Err(String::from(
+    /// Validates a message from a client and returns the valid move it describes or an error
+    /// message
+    pub fn validate_message(&mut self, content: &ReceivedData, addr: &SocketAddr) -> Result<GameMove, String> {
+        Err(String::from(
                 if !self.game_started {
                     "Game has not started yet"
-                } else if self.players.get(addr).is_none() {
+                } else if self.result != GameResult::Ongoing {
+                    "Game is over"
+                } else if !self.players.contains_key(addr) {
                     "Client has not connected"
-                } else if !(self.current_player.unwrap() == self.players.get(addr).unwrap().unwrap()) {
-                    "It is not you turn"
-                } else if !self.is_valid_move(content, addr) {
-                    "Move is not valid"
-                } else { return Ok(()); }
-                ));
+                } else if let Some(Some(color)) = self.players.get(addr) {
+                    if self.current_player != Some(*color) {
+                        "It is not you turn"
+                    } else if let Some(valid_move) = self.find_valid_move(content) {
+                        return Ok(valid_move);
+                    } else { "Move is not valid" }
+                } else { "Spectators cannot move" }
+                ))
     }
 
-    /// Checks if the move given move is valid
-    fn is_valid_move(&mut self, content: &ReceivedData, addr: &SocketAddr) -> bool {
+    /// Validates an action that a player can make regardless of whose turn it is, and returns
+    /// the color of the player or an error message
+    pub fn validate_player(&self, addr: &SocketAddr) -> Result<PieceColor, String> {
+        Err(String::from(
+                if !self.game_started {
+                    "Game has not started yet"
+                } else if self.result != GameResult::Ongoing {
+                    "Game is over"
+                } else if let Some(Some(color)) = self.players.get(addr) {
+                    if self.eliminated.contains(color) {
+                        "You have been eliminated"
+                    } else {
+                        return Ok(*color);
+                    }
+                } else { "Spectators cannot do that" }
+                ))
+    }
+
+    /// Finds the valid move of the player to move matching the given move
+    fn find_valid_move(&self, content: &ReceivedData) -> Option<GameMove> {
         match content {
             ReceivedData::Move(given_move) => {
-                generate_moves(self.players.get(addr).unwrap().unwrap(),
-                &self.rules.borrow().pieces,
-                &self.board.borrow(),
-                Some((&self.ast, &self.engine)))
+                self.position_moves().legal
                     .iter()
-                    .find(|valid_move| &given_move == valid_move).is_some()
+                    .find(|valid_move| valid_move.matches(given_move))
+                    .cloned()
             },
+            _ => None,
         }
     }
 
@@ -0,0 +1,371 @@
+/// Tests for the lobby
+#[cfg(test)]
+mod test;
+
+use crate::{
+    Error,
+    args::Arguments,
+    game::{Game, SavedGame},
+    rules::RulesEnv,
+    logic::{
+        color::PieceColor,
+        search::SearchLimits,
+    },
+    networking::{
+        Msg,
+        MsgData,
+        ClientList,
+        receiveddata::{ReceivedData, NewGame, NewBot},
+        socketdata::{SocketData, GameInfo},
+    },
+};
+use std::{
+    net::SocketAddr,
+    collections::HashMap,
+    path::PathBuf,
+    fs,
+    time::{Duration, Instant},
+};
+
+/// Name of the game created from the command line
+pub const DEFAULT_GAME: &str = "default";
+
+/// Keeps track of every game hosted by the server and which game each client has joined
+pub struct Lobby {
+    /// Connections to every client, including clients that haven't joined a game
+    clients: ClientList,
+    /// Games mapped by their name
+    games: HashMap<String, Game<'static>>,
+    /// Name of the game each client has joined
+    rooms: HashMap<SocketAddr, String>,
+    /// Directory containing the rules files clients can create games from
+    games_dir: PathBuf,
+    /// Command line options used when creating games
+    options: Arguments,
+}
+
+impl Lobby {
+
+    /// Creates a new lobby without any games
+    pub fn new(clients: ClientList, options: &Arguments) -> Self {
+        Self {
+            clients,
+            games: HashMap::new(),
+            rooms: HashMap::new(),
+            games_dir: PathBuf::from(&options.games_dir),
+            options: options.clone(),
+        }
+    }
+
+    /// Creates a new game named `name` from the rules file at `path`
+    pub fn create_game(&mut self, name: &str, path: &str) -> Result<(), Error> {
+        info!("Creating game {}", name);
+        let game = self.load_game(path)?;
+        self.games.insert(name.to_string(), game);
+        Ok(())
+    }
+
+    /// Restores a game from a save file
+    pub fn resume_game(&mut self, file: &str) -> Result<(), Error> {
+        let saved: SavedGame = serde_json::from_str(&fs::read_to_string(file)?)?;
+        info!("Resuming game {}", saved.name);
+        let mut game = self.load_game(&saved.rules)?;
+        let name = saved.name.clone();
+        game.restore(saved)?;
+        // The save might be from a server that allowed longer searches
+        game.cap_bots(self.max_bot_limits());
+        self.games.insert(name, game);
+        Ok(())
+    }
+
+    /// Gives bots the seats of `colors` in a game
+    pub async fn add_bots(&mut self, name: &str, colors: &[PieceColor]) -> Result<(), String> {
+        let limits = self.bot_limits(None, None);
+        let game = self.games.get_mut(name).ok_or("Game does not exist")?;
+        for color in colors {
+            game.add_bot(Some(*color), limits).await?;
+        }
+        Ok(())
+    }
+
+    /// Checks if a game with the given name exists
+    pub fn has_game(&self, name: &str) -> bool {
+        self.games.contains_key(name)
+    }
+
+    /// Creates a game from the rules file at `path`
+    fn load_game(&self, path: &str) -> Result<Game<'static>, Error> {
+        let rules = RulesEnv::new(path, &self.options.limits)?;
+        debug!("Board: {:?}", rules.rules.borrow().board);
+        // The time control from the command line is used if the rules don't set one
+        if rules.rules.borrow().time_control.is_none() {
+            rules.rules.borrow_mut().time_control = self.options.time_control;
+        }
+        let grace_period = Duration::from_secs(self.options.grace_period);
+        Ok(Game::new(self.clients.clone(), rules, grace_period))
+    }
+
+    /// Writes a game to the save directory and its record to the record directory, if they are
+    /// given
+    fn save_game(&self, name: &str) {
+        let game = match self.games.get(name) {
+            Some(game) => game,
+            None => return,
+        };
+        // Game names are chosen by clients, so they are reduced to safe file names
+        let file_name: String = name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+        if let Some(dir) = &self.options.save_dir {
+            let result = game.save(name)
+                .and_then(|saved| Ok(serde_json::to_string(&saved)?))
+                .and_then(|json| write_file(dir, &format!("{}.json", file_name), &json));
+            if let Err(e) = result {
+                error!("Failed to save game {}: {}", name, e);
+            }
+        }
+        // Records are named after the start of the game, so earlier games with the same name
+        // aren't overwritten
+        if let (Some(dir), Some(start_time)) = (&self.options.record_dir, game.start_time()) {
+            let record = game.record(name).to_string();
+            if let Err(e) = write_file(dir, &format!("{}-{}.pgn", file_name, start_time), &record) {
+                error!("Failed to write record of game {}: {}", name, e);
+            }
+        }
+    }
+
+    /// Saves every game and closes the connections to the clients
+    pub async fn shutdown(&mut self) {
+        info!("Server is shutting down");
+        for name in self.games.keys() {
+            self.save_game(name);
+        }
+        for client in self.clients.lock().await.values_mut() {
+            client.close("Server is shutting down").await;
+        }
+    }
+
+    /// Gets the earliest time a player in any game runs out of time
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.games.values().filter_map(|game| game.deadline()).min()
+    }
+
+    /// Updates the games that are due, ending games where the player to move has run out of
+    /// time and letting bots move. Games whose players didn't rejoin are closed
+    pub async fn update_games(&mut self) {
+        let names: Vec<String> = self.games.iter()
+            .filter(|(_, game)| matches!(game.deadline(), Some(deadline) if deadline <= Instant::now()))
+            .map(|(name, _)| name.clone())
+            .collect();
+        let now = Instant::now();
+        for name in names {
+            if let Some(game) = self.games.get_mut(&name) {
+                game.update(now).await;
+                self.save_game(&name);
+            }
+        }
+        self.games.retain(|name, game| !game.is_empty() || name == DEFAULT_GAME);
+    }
+
+    /// Handles a message received from the networking thread
+    pub async fn handle_msg(&mut self, msg: Msg) {
+        match msg.data {
+            MsgData::NewConnection => self.new_connection(&msg.addr).await,
+            MsgData::ClosedConnection => self.closed_connection(&msg.addr).await,
+            MsgData::Data(content) => self.handle_message(&content, &msg.addr).await,
+        }
+    }
+
+    /// Sends the list of games to a newly connected client
+    async fn new_connection(&mut self, addr: &SocketAddr) {
+        info!("Connection established with {}", addr);
+        self.send(addr, &SocketData::GameList(self.game_list())).await;
+    }
+
+    /// Removes the client from its game and from the client list
+    async fn closed_connection(&mut self, addr: &SocketAddr) {
+        self.leave_game(addr);
+        let mut clients = self.clients.lock().await;
+        clients.remove(addr);
+        debug!("{} clients connected", clients.len());
+    }
+
+    /// Handles lobby actions and passes every other message on to the game of the client
+    async fn handle_message(&mut self, content: &ReceivedData, addr: &SocketAddr) {
+        match content {
+            ReceivedData::ListGames => self.send(addr, &SocketData::GameList(self.game_list())).await,
+            ReceivedData::CreateGame(new_game) => {
+                if let Err(err_msg) = self.create_requested_game(new_game) {
+                    self.send(addr, &SocketData::InvalidMessage(err_msg)).await;
+                    return;
+                }
+                self.join_game(&new_game.name, addr).await;
+            },
+            ReceivedData::JoinGame(name) => self.join_game(name, addr).await,
+            ReceivedData::Rejoin(token) => self.rejoin(token, addr).await,
+            ReceivedData::AddBot(new_bot) => self.add_requested_bot(new_bot, addr).await,
+            ReceivedData::GetRecord => {
+                let record = self.rooms.get(addr)
+                    .and_then(|name| self.games.get(name).map(|game| game.record(name).to_string()));
+                let data = match record {
+                    Some(record) => SocketData::Record(record),
+                    None => SocketData::InvalidMessage(String::from("You have not joined a game")),
+                };
+                self.send(addr, &data).await;
+            },
+            ReceivedData::LeaveGame => {
+                self.leave_game(addr);
+                self.send(addr, &SocketData::GameList(self.game_list())).await;
+            },
+            _ => {
+                if let Some(name) = self.rooms.get(addr).cloned() {
+                    if let Some(game) = self.games.get_mut(&name) {
+                        game.handle_message(content, addr).await;
+                        self.save_game(&name);
+                        return;
+                    }
+                }
+                self.send(addr, &SocketData::InvalidMessage(
+                        String::from("You have not joined a game"))).await;
+            },
+        }
+    }
+
+    /// Adds a bot requested by a client to the game of the client
+    async fn add_requested_bot(&mut self, new_bot: &NewBot, addr: &SocketAddr) {
+        let limits = self.bot_limits(new_bot.depth, new_bot.time);
+        let result = match (self.rooms.get(addr), new_bot.color) {
+            (None, _) => Err(String::from("You have not joined a game")),
+            (Some(_), Some(id)) if PieceColor::from_id(id).is_none() => Err(String::from("Invalid color")),
+            (Some(name), id) => {
+                let name = name.clone();
+                match self.games.get_mut(&name) {
+                    Some(game) => {
+                        let result = game.add_bot(id.and_then(PieceColor::from_id), limits).await;
+                        self.save_game(&name);
+                        result
+                    },
+                    None => Err(String::from("Game does not exist")),
+                }
+            },
+        };
+        if let Err(err_msg) = result {
+            self.send(addr, &SocketData::InvalidMessage(err_msg)).await;
+        }
+    }
+
+    /// Gets the search limits for a bot, using the command line options for missing limits and
+    /// capping the requested limits at the ones allowed by the server
+    fn bot_limits(&self, depth: Option<usize>, time: Option<f64>) -> SearchLimits {
+        let max = self.max_bot_limits();
+        SearchLimits {
+            depth: depth.unwrap_or(self.options.bot_depth),
+            // Times too long for a `Duration` are capped like any other long time
+            time: time.filter(|time| *time > 0.0)
+                .map(|time| Duration::try_from_secs_f64(time).unwrap_or(max.time.unwrap())),
+        }.capped(max)
+    }
+
+    /// Gets the deepest and longest search the server lets bots make
+    fn max_bot_limits(&self) -> SearchLimits {
+        SearchLimits {
+            depth: self.options.max_bot_depth.max(self.options.bot_depth),
+            time: Some(self.options.bot_time),
+        }
+    }
+
+    /// Creates a game requested by a client or returns an error message
+    fn create_requested_game(&mut self, new_game: &NewGame) -> Result<(), String> {
+        if new_game.name.is_empty() {
+            return Err(String::from("Game name cannot be empty"));
+        } else if self.games.contains_key(&new_game.name) {
+            return Err(String::from("A game with that name already exists"));
+        }
+        // Only allow plain file names, so clients can't load files outside of the games directory
+        if new_game.rules.is_empty() || !new_game.rules.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(String::from("Invalid rules name"));
+        }
+        let path = self.games_dir.join(format!("{}.rhai", new_game.rules));
+        if !path.is_file() {
+            return Err(String::from("Rules do not exist"));
+        }
+        self.create_game(&new_game.name, &path.to_string_lossy()).map_err(|e| {
+            error!("{}", e);
+            String::from("Failed to load rules")
+        })
+    }
+
+    /// Moves a client to the game named `name`
+    async fn join_game(&mut self, name: &str, addr: &SocketAddr) {
+        if !self.games.contains_key(name) {
+            self.send(addr, &SocketData::InvalidMessage(String::from("Game does not exist"))).await;
+            return;
+        } else if self.rooms.get(addr).map(String::as_str) == Some(name) {
+            // Leaving first could close the game, and joining again would take another seat
+            self.send(addr, &SocketData::InvalidMessage(String::from("You have already joined that game"))).await;
+            return;
+        }
+        self.leave_game(addr);
+        if let Some(game) = self.games.get_mut(name) {
+            info!("{} joined game {}", addr, name);
+            self.rooms.insert(*addr, name.to_string());
+            game.new_connection(addr).await;
+            self.save_game(name);
+        }
+    }
+
+    /// Gives a client its seat back in the game the session token belongs to
+    async fn rejoin(&mut self, token: &str, addr: &SocketAddr) {
+        let name = match self.games.iter().find(|(_, game)| game.has_seat(token)) {
+            Some((name, _)) => name.clone(),
+            None => {
+                self.send(addr, &SocketData::InvalidMessage(String::from("Invalid token"))).await;
+                return;
+            },
+        };
+        if self.rooms.get(addr) != Some(&name) {
+            self.leave_game(addr);
+            self.rooms.insert(*addr, name.clone());
+        }
+        if let Some(game) = self.games.get_mut(&name) {
+            game.rejoin(addr, token).await;
+            self.save_game(&name);
+        }
+    }
+
+    /// Removes a client from the game it has joined. Games created by clients are closed when
+    /// the last client leaves.
+    fn leave_game(&mut self, addr: &SocketAddr) {
+        if let Some(name) = self.rooms.remove(addr) {
+            if let Some(game) = self.games.get_mut(&name) {
+                game.closed_connection(addr);
+                if game.is_empty() && name != DEFAULT_GAME {
+                    info!("Closing game {}", name);
+                    self.games.remove(&name);
+                }
+            }
+        }
+    }
+
+    /// Generates a summary of every game
+    fn game_list(&self) -> Vec<GameInfo> {
+        let mut names: Vec<&String> = self.games.keys().collect();
+        names.sort();
+        names.into_iter()
+            .map(|name| self.games[name].info(name))
+            .collect()
+    }
+
+    /// Sends data to a single client
+    async fn send(&self, addr: &SocketAddr, data: &SocketData) {
+        if let Some(client) = self.clients.lock().await.get_mut(addr) {
+            client.send_socket(data).await;
+        }
+    }
+}
+
+/// Writes a file to a directory, creating the directory if needed
+fn write_file(dir: &str, file_name: &str, contents: &str) -> Result<(), Error> {
+    fs::create_dir_all(dir)?;
+    Ok(fs::write(PathBuf::from(dir).join(file_name), contents)?)
+}
@@ -0,0 +1,112 @@
+use super::{Lobby, DEFAULT_GAME};
+use crate::{
+    args::Arguments,
+    networking::{
+        Msg,
+        MsgData,
+        receiveddata::{ReceivedData, NewGame},
+    },
+};
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    collections::HashMap,
+};
+use structopt::StructOpt;
+use tokio::sync::Mutex;
+
+/// Test that clients can create games from the games directory, and that invalid games are
+/// turned away
+#[tokio::test]
+async fn create_games() {
+    let mut lobby = helper_lobby();
+    let alice = helper_addr(1);
+    helper_send(&mut lobby, alice, ReceivedData::CreateGame(helper_new_game("blitz", "standard"))).await;
+    assert!(lobby.has_game("blitz"));
+    assert_eq!(lobby.rooms[&alice], "blitz");
+
+    let invalid = [
+        (helper_new_game("", "standard"), "Game name cannot be empty"),
+        (helper_new_game("blitz", "standard"), "A game with that name already exists"),
+        (helper_new_game("other", "../game"), "Invalid rules name"),
+        (helper_new_game("other", ""), "Invalid rules name"),
+        (helper_new_game("other", "missing"), "Rules do not exist"),
+    ];
+    for (new_game, error) in invalid.iter() {
+        assert_eq!(lobby.create_requested_game(new_game), Err(error.to_string()));
+    }
+    assert!(!lobby.has_game("other"));
+}
+
+/// Test that the game list shows every game sorted by name with its players
+#[tokio::test]
+async fn list_games() {
+    let mut lobby = helper_lobby();
+    helper_send(&mut lobby, helper_addr(1), ReceivedData::CreateGame(helper_new_game("blitz", "standard"))).await;
+    helper_send(&mut lobby, helper_addr(2), ReceivedData::CreateGame(helper_new_game("atomic", "atomic"))).await;
+    let list = serde_json::to_value(lobby.game_list()).unwrap();
+    assert_eq!(list, serde_json::json!([
+        { "name": "atomic", "variant": "Atomic Chess", "players": 1, "seats": 2, "started": false },
+        { "name": "blitz", "variant": "Chess", "players": 1, "seats": 2, "started": false },
+        { "name": DEFAULT_GAME, "variant": "Chess", "players": 0, "seats": 2, "started": false },
+    ]));
+}
+
+/// Test that clients can join games, that games start once every seat is taken and that games
+/// created by clients are closed when the last client leaves
+#[tokio::test]
+async fn join_games() {
+    let mut lobby = helper_lobby();
+    let (alice, bob, carol) = (helper_addr(1), helper_addr(2), helper_addr(3));
+    helper_send(&mut lobby, alice, ReceivedData::CreateGame(helper_new_game("blitz", "standard"))).await;
+    helper_send(&mut lobby, bob, ReceivedData::JoinGame(String::from("blitz"))).await;
+    helper_send(&mut lobby, carol, ReceivedData::JoinGame(String::from("blitz"))).await;
+    let info = serde_json::to_value(lobby.games["blitz"].info("blitz")).unwrap();
+    assert_eq!(info["players"], 2);
+    assert_eq!(info["started"], true);
+    assert_eq!(lobby.rooms[&carol], "blitz");
+
+    // Joining a game that doesn't exist or the same game again changes nothing
+    helper_send(&mut lobby, carol, ReceivedData::JoinGame(String::from("missing"))).await;
+    helper_send(&mut lobby, alice, ReceivedData::JoinGame(String::from("blitz"))).await;
+    assert_eq!(lobby.rooms[&carol], "blitz");
+    assert_eq!(serde_json::to_value(lobby.games["blitz"].info("blitz")).unwrap()["players"], 2);
+
+    // Moving to another game leaves the first one
+    helper_send(&mut lobby, carol, ReceivedData::JoinGame(String::from(DEFAULT_GAME))).await;
+    assert_eq!(lobby.rooms[&carol], DEFAULT_GAME);
+
+    // The seats of players that leave are kept for the grace period before the game is closed
+    for addr in [alice, bob, carol].iter() {
+        helper_send(&mut lobby, *addr, ReceivedData::LeaveGame).await;
+    }
+    assert!(lobby.rooms.is_empty());
+    assert!(lobby.has_game("blitz"));
+    lobby.update_games().await;
+    assert!(!lobby.has_game("blitz"));
+    assert!(lobby.has_game(DEFAULT_GAME));
+}
+
+/// Creates a lobby with the default game, which loads rules from the games directory. Seats
+/// of players that leave are released right away
+fn helper_lobby() -> Lobby {
+    let options = Arguments::from_iter(&["test", "--games-dir", "games", "--grace-period", "0"]);
+    let mut lobby = Lobby::new(Arc::new(Mutex::new(HashMap::new())), &options);
+    lobby.create_game(DEFAULT_GAME, "games/standard.rhai").unwrap();
+    lobby
+}
+
+/// Creates the address of a client
+fn helper_addr(port: u16) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+/// Creates the data for a new game
+fn helper_new_game(name: &str, rules: &str) -> NewGame {
+    NewGame { name: name.to_string(), rules: rules.to_string() }
+}
+
+/// Passes data from a client to the lobby
+async fn helper_send(lobby: &mut Lobby, addr: SocketAddr, content: ReceivedData) {
+    lobby.handle_msg(Msg { addr, data: MsgData::Data(content) }).await;
+}
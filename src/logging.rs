@@ -23,9 +23,10 @@ pub fn startup_banner() {
              "#.green());
 }
 
-/// Initializes logger and display startup banner
+/// Initializes logger and display startup banner. The banner is not shown when running a
+/// subcommand
 pub fn initialize_logging(options: &Arguments) {
-    if !options.no_startup_banner {
+    if !options.no_startup_banner && options.command.is_none() {
         startup_banner();
     }
     TermLogger::init(options.loglevel, Config::default(), TerminalMode::Mixed).unwrap();
@@ -0,0 +1,156 @@
+use pest::Parser;
+use crate::error::{Error, parse_position};
+use crate::logic::movement::{Movement, Direction, Distance};
+
+/// Pest parser for Betza notation
+#[derive(Parser, Debug)]
+#[grammar = "parsers/betza.pest"]
+struct BetzaParser;
+
+/// Parses the movement rules of a piece from Betza notation, like `WfcF` or `mRcpR`. Each part
+/// is an atom, which is a leap or a compound of leaps, with modifiers in front of it:
+///
+/// * `f`, `b`, `l`, `r`, `s` and `v` limit the directions to forward, backward, left, right,
+///   sideways and vertical. Doubled letters keep the narrow moves of oblique atoms, and `fs`
+///   or `fl` style pairs combine a vertical and a horizontal direction
+/// * `m` only moves, `c` only captures and `i` only moves pieces that haven't moved
+/// * `n` makes leaps lame, so they are blocked by pieces on the way
+/// * `p` hops over a piece and lands anywhere behind it, `g` lands right behind it
+///
+/// A doubled atom rides along the leap, like `NN` for the nightrider, and a number after it
+/// limits how far it rides. Directions are given for square boards
+pub fn parse(input: &str) -> Result<Vec<Movement>, Error> {
+    let betza = match BetzaParser::parse(Rule::betza, input) {
+        Ok(mut x) => x.next().unwrap(),
+        Err(e) => return Err(Error::BetzaParse { input: input.to_string(), position: parse_position(&e) }),
+    };
+    let mut moves = vec![];
+    for part in betza.into_inner().filter(|pair| pair.as_rule() == Rule::part) {
+        let mut template = Movement::new();
+        let mut directions = String::new();
+        let mut atom = "";
+        let mut distance = None;
+        for pair in part.into_inner() {
+            match pair.as_rule() {
+                Rule::modifier => match pair.as_str() {
+                    "m" => template.nocapture = true,
+                    "c" => template.capture = true,
+                    "i" => template.initial = true,
+                    "n" => template.lame = true,
+                    "p" => template.hopper = true,
+                    "g" => {
+                        template.hopper = true;
+                        template.grasshopper = true;
+                    },
+                    direction => directions.push_str(direction),
+                },
+                Rule::atom => atom = pair.as_str(),
+                Rule::rider => distance = Some(Distance::N),
+                Rule::range => distance = Some(Distance::Range {
+                    min: 1,
+                    max: pair.as_str().parse::<usize>().map_err(|_| Error::BetzaParse {
+                        input: input.to_string(),
+                        position: pair.as_span().start() + 1,
+                    })?,
+                }),
+                _ => unreachable!(),
+            }
+        }
+        // Moves that both move and capture are normal moves
+        if template.capture && template.nocapture {
+            template.capture = false;
+            template.nocapture = false;
+        }
+        for (leap, rides) in components(atom) {
+            let mut movement = template.clone();
+            movement.distance = match &distance {
+                Some(distance) => distance.clone(),
+                None if rides => Distance::N,
+                None => Distance::Set(1),
+            };
+            let steps = symmetric(leap).into_iter()
+                .filter(|step| allowed(*step, &directions))
+                .collect();
+            movement.direction = vec![Direction::Leap(steps)];
+            moves.push(movement);
+        }
+    }
+    Ok(moves)
+}
+
+/// Gets the leaps an atom is made of, and whether the atom rides along them
+fn components(atom: &str) -> Vec<((i32, i32), bool)> {
+    match atom {
+        "W" => vec![((1, 0), false)],
+        "F" => vec![((1, 1), false)],
+        "D" => vec![((2, 0), false)],
+        "N" => vec![((1, 2), false)],
+        "A" => vec![((2, 2), false)],
+        "H" => vec![((3, 0), false)],
+        "C" => vec![((1, 3), false)],
+        "Z" => vec![((2, 3), false)],
+        "G" => vec![((3, 3), false)],
+        "K" => vec![((1, 0), false), ((1, 1), false)],
+        "R" => vec![((1, 0), true)],
+        "B" => vec![((1, 1), true)],
+        "Q" => vec![((1, 0), true), ((1, 1), true)],
+        _ => unreachable!(),
+    }
+}
+
+/// Gets every step of a leap, turned and mirrored in all directions
+fn symmetric((a, b): (i32, i32)) -> Vec<(i32, i32)> {
+    let mut steps = vec![];
+    for (x, y) in &[(a, b), (b, a)] {
+        for (sx, sy) in &[(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            steps.push((x * sx, y * sy));
+        }
+    }
+    steps.sort();
+    steps.dedup();
+    steps
+}
+
+/// Checks if a step for a piece facing up is in one of the directions given by direction
+/// letters. Each `f`, `b` or `v` can be followed by an `l`, `r` or `s`, and both have to match
+fn allowed(step: (i32, i32), directions: &str) -> bool {
+    if directions.is_empty() {
+        return true;
+    }
+    let forward = -step.1;
+    let side = step.0;
+    let letters: Vec<char> = directions.chars().collect();
+    let mut i = 0;
+    let mut allowed = false;
+    while i < letters.len() {
+        let mut matches = true;
+        let mut first = true;
+        loop {
+            let letter = letters[i];
+            let narrow = letters.get(i + 1) == Some(&letter);
+            i += if narrow { 2 } else { 1 };
+            matches &= match letter {
+                'f' => forward > 0,
+                'b' => forward < 0,
+                'v' => forward != 0 && forward.abs() >= side.abs(),
+                'l' => side < 0,
+                'r' => side > 0,
+                _ => side != 0 && side.abs() >= forward.abs(),
+            };
+            if narrow {
+                matches &= match letter {
+                    'f' | 'b' | 'v' => forward.abs() > side.abs(),
+                    _ => side.abs() > forward.abs(),
+                };
+            }
+            let pairs = first && "fbv".contains(letter)
+                && matches!(letters.get(i), Some(next) if "lrs".contains(*next));
+            first = false;
+            if !pairs {
+                break;
+            }
+        }
+        allowed |= matches;
+    }
+    allowed
+}
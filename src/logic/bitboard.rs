@@ -0,0 +1,298 @@
+use crate::logic::{
+    board::GameBoard,
+    color::{PieceColor, Teams},
+    geometry::{Geometry, Wrap},
+    move_gen::{GameMove, Squares, piece_attacks, piece_reaches},
+    piece::{Piece, PieceList},
+};
+
+/// Set of squares with one bit per square, numbered row by row from the top left corner
+pub trait Bitboard: Copy + Default {
+    /// Largest number of squares the bitboard can hold
+    const SQUARES: usize;
+
+    fn contains(&self, index: usize) -> bool;
+    fn insert(&mut self, index: usize);
+    fn remove(&mut self, index: usize);
+    /// Removes and returns the lowest square in the set
+    fn pop(&mut self) -> Option<usize>;
+}
+
+impl Bitboard for u64 {
+    const SQUARES: usize = 64;
+
+    fn contains(&self, index: usize) -> bool {
+        self & (1 << index) != 0
+    }
+
+    fn insert(&mut self, index: usize) {
+        *self |= 1 << index;
+    }
+
+    fn remove(&mut self, index: usize) {
+        *self &= !(1 << index);
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        if *self == 0 {
+            return None;
+        }
+        let index = self.trailing_zeros() as usize;
+        *self &= *self - 1;
+        Some(index)
+    }
+}
+
+/// Bitboard for boards of up to 16x16 squares
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Wide([u64; 4]);
+
+impl Bitboard for Wide {
+    const SQUARES: usize = 256;
+
+    fn contains(&self, index: usize) -> bool {
+        self.0[index / 64].contains(index % 64)
+    }
+
+    fn insert(&mut self, index: usize) {
+        self.0[index / 64].insert(index % 64);
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.0[index / 64].remove(index % 64);
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        for (i, word) in self.0.iter_mut().enumerate() {
+            if let Some(index) = word.pop() {
+                return Some(i * 64 + index);
+            }
+        }
+        None
+    }
+}
+
+/// Bitboard representation of a `GameBoard`. Pieces are stored as references to their type, so
+/// moves can be made and checks found without cloning the symbols of every piece
+#[derive(Debug, Clone)]
+pub struct Position<'a, B: Bitboard> {
+    pub width: usize,
+    pub height: usize,
+    /// Squares with a piece of each color, indexed by color id
+    colors: [B; PieceColor::COUNT],
+    /// Squares with a piece that has moved
+    moved: B,
+    /// Squares that aren't part of the board
+    voids: B,
+    /// Type of the piece on each square
+    kinds: Vec<Option<&'a Piece>>,
+    /// Which colors are allied
+    teams: Teams,
+    geometry: Geometry,
+    wrap: Wrap,
+}
+
+impl<'a, B: Bitboard> Position<'a, B> {
+
+    /// Creates a position from a board. Returns None if the board is too large for the bitboard
+    pub fn from_board(board: &GameBoard, pieces: &'a PieceList) -> Option<Position<'a, B>> {
+        if board.width * board.height > B::SQUARES {
+            return None;
+        }
+        let mut position = Position {
+            width: board.width,
+            height: board.height,
+            colors: [B::default(); PieceColor::COUNT],
+            moved: B::default(),
+            voids: B::default(),
+            kinds: vec![None; board.width * board.height],
+            teams: board.teams,
+            geometry: board.geometry,
+            wrap: board.wrap,
+        };
+        for (x, y) in board.void_squares() {
+            let index = position.index(x, y);
+            position.voids.insert(index);
+        }
+        for (y, row) in board.board.iter().enumerate() {
+            for (x, square) in row.iter().enumerate() {
+                if let Some(piece) = square {
+                    let index = position.index(x, y);
+                    position.colors[piece.color as usize].insert(index);
+                    if piece.has_moved {
+                        position.moved.insert(index);
+                    }
+                    position.kinds[index] = pieces.get(&piece.symbol);
+                }
+            }
+        }
+        Some(position)
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+
+    /// Moves the piece on `from` to `to`, replacing any piece on `to`
+    fn move_piece(&mut self, from: (usize, usize), to: (usize, usize)) {
+        let from = self.index(from.0, from.1);
+        let to = self.index(to.0, to.1);
+        self.clear(to);
+        for color in &mut self.colors {
+            if color.contains(from) {
+                color.remove(from);
+                color.insert(to);
+            }
+        }
+        self.moved.remove(from);
+        self.moved.insert(to);
+        self.kinds[to] = self.kinds[from].take();
+    }
+
+    fn clear(&mut self, index: usize) {
+        for color in &mut self.colors {
+            color.remove(index);
+        }
+        self.moved.remove(index);
+        self.kinds[index] = None;
+    }
+
+    /// Makes a move the same way as `GameBoard::do_move`. Returns false if there is no piece to
+    /// move
+    pub fn do_move(&mut self, mov: &GameMove, pieces: &'a PieceList) -> bool {
+        if mov.from.0 >= self.width || mov.to.0 >= self.width
+            || mov.from.1 >= self.height || mov.to.1 >= self.height
+            || self.kinds[self.index(mov.from.0, mov.from.1)].is_none() {
+            return false;
+        }
+        self.move_piece(mov.from, mov.to);
+        if let Some(symbol) = &mov.promotion {
+            let index = self.index(mov.to.0, mov.to.1);
+            self.kinds[index] = pieces.get(symbol);
+        }
+        if let Some((from, to)) = mov.castle {
+            self.move_piece(from, to);
+        }
+        if let Some((x, y)) = mov.captures {
+            let index = self.index(x, y);
+            self.clear(index);
+        }
+        true
+    }
+
+    /// Checks if any piece of a color not allied with `color` can move to (`x`, `y`)
+    pub fn is_attacked(&self, color: PieceColor, x: usize, y: usize) -> bool {
+        for (id, attackers) in self.colors.iter().enumerate() {
+            let attacker = match PieceColor::from_id(id as u8) {
+                Some(attacker) if !self.teams.allied(attacker, color) => attacker,
+                _ => continue,
+            };
+            let mut squares = *attackers;
+            while let Some(index) = squares.pop() {
+                let kind = match self.kinds[index] {
+                    Some(kind) => kind,
+                    None => continue,
+                };
+                if piece_reaches(attacker, index % self.width, index / self.width, kind, self, (x, y)) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Gets the squares attacked by the pieces of a player
+    pub fn attacks(&self, color: PieceColor) -> B {
+        let mut attacked = B::default();
+        self.add_attacks(color, &mut attacked);
+        attacked
+    }
+
+    /// Adds the squares attacked by the pieces of a player to `attacked`
+    fn add_attacks(&self, color: PieceColor, attacked: &mut B) {
+        let mut squares = self.colors[color as usize];
+        while let Some(index) = squares.pop() {
+            if let Some(kind) = self.kinds[index] {
+                piece_attacks(color, index % self.width, index / self.width, kind, self, &mut |x, y| {
+                    attacked.insert(y * self.width + x);
+                });
+            }
+        }
+    }
+
+    /// Gets the squares attacked by the pieces of a player as coordinates, sorted row by row
+    pub fn attacked_squares(&self, color: PieceColor) -> Vec<(usize, usize)> {
+        let mut attacked = self.attacks(color);
+        let mut squares = vec![];
+        while let Some(index) = attacked.pop() {
+            squares.push((index % self.width, index / self.width));
+        }
+        squares
+    }
+
+    /// Checks if any royal piece of a player is attacked by an enemy. With several royal pieces
+    /// the squares attacked by the enemies are found once, instead of looking for attackers of each
+    /// piece
+    pub fn in_check(&self, color: PieceColor) -> bool {
+        let mut royal = B::default();
+        let mut count = 0;
+        let mut squares = self.colors[color as usize];
+        while let Some(index) = squares.pop() {
+            if matches!(self.kinds[index], Some(kind) if kind.kingstatus) {
+                royal.insert(index);
+                count += 1;
+            }
+        }
+        if count > 1 {
+            let mut attacked = B::default();
+            for attacker in (0..PieceColor::COUNT as u8).filter_map(PieceColor::from_id) {
+                if !self.teams.allied(attacker, color) {
+                    self.add_attacks(attacker, &mut attacked);
+                }
+            }
+            while let Some(index) = royal.pop() {
+                if attacked.contains(index) {
+                    return true;
+                }
+            }
+            return false;
+        }
+        match royal.pop() {
+            Some(index) => self.is_attacked(color, index % self.width, index / self.width),
+            None => false,
+        }
+    }
+}
+
+impl<B: Bitboard> Squares for Position<'_, B> {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn color_at(&self, x: usize, y: usize) -> Option<PieceColor> {
+        let index = self.index(x, y);
+        (0..PieceColor::COUNT as u8)
+            .find(|id| self.colors[*id as usize].contains(index))
+            .and_then(PieceColor::from_id)
+    }
+
+    fn has_moved(&self, x: usize, y: usize) -> bool {
+        self.moved.contains(self.index(x, y))
+    }
+
+    fn allied(&self, a: PieceColor, b: PieceColor) -> bool {
+        self.teams.allied(a, b)
+    }
+
+    fn is_void(&self, x: usize, y: usize) -> bool {
+        self.voids.contains(self.index(x, y))
+    }
+
+    fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    fn wrap(&self) -> Wrap {
+        self.wrap
+    }
+}
@@ -1,16 +1,18 @@
 use pest::Parser;
+use serde::{Serialize, Deserialize};
 use std::fmt;
 use crate::{
-    error::Error,
+    error::{Error, parse_position},
     logic::{
         piece::*,
-        color::PieceColor,
+        color::{PieceColor, Teams},
+        geometry::{Geometry, Wrap},
         move_gen::GameMove,
     },
 };
 
 /// Struct for containing the board of a game, and the width and height
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GameBoard {
     /// Horizontal size of board
     pub width: usize,
@@ -19,6 +21,21 @@ pub struct GameBoard {
     /// Vectors of each row and cell in the board. First vector is vertical coordinates and second
     /// row is horizontal. The Option is None, if there isn't a piece in that position.
     pub board: Vec<Vec<Option<GamePiece>>>,
+    /// The last move made on the board
+    pub last_move: Option<GameMove>,
+    /// Which colors are allied
+    #[serde(default)]
+    pub teams: Teams,
+    /// Shape of the squares of the board
+    #[serde(default)]
+    pub geometry: Geometry,
+    /// Edges of the board that pieces can move across
+    #[serde(default)]
+    pub wrap: Wrap,
+    /// Squares that aren't part of the board, indexed like `board`. Pieces can't move to or
+    /// through them. Empty if the board has no voids
+    #[serde(default)]
+    pub voids: Vec<Vec<bool>>,
 }
 
 /// Simple struct for generating a gameboard from ffen-string.
@@ -27,11 +44,26 @@ pub struct GameBoard {
 pub struct GameBoardBuilder {
     pub current_x: usize,
     pub game_board: GameBoard,
+    /// Coordinates of the voids pushed to the board
+    voids: Vec<(usize, usize)>,
 }
 
 impl GameBoardBuilder {
-    /// Returns the build GameBoard
-    pub fn get_board(self) -> GameBoard {
+    /// Returns the build GameBoard. Rows shorter than the widest row are filled with voids
+    pub fn get_board(mut self) -> GameBoard {
+        let board = &mut self.game_board;
+        for (y, row) in board.board.iter_mut().enumerate() {
+            while row.len() < board.width {
+                self.voids.push((row.len(), y));
+                row.push(None);
+            }
+        }
+        if !self.voids.is_empty() {
+            board.voids = vec![vec![false; board.width]; board.height];
+            for (x, y) in self.voids {
+                board.voids[y][x] = true;
+            }
+        }
         self.game_board
     }
 
@@ -40,6 +72,7 @@ impl GameBoardBuilder {
         GameBoardBuilder {
             current_x: 0,
             game_board: GameBoard::new(),
+            voids: vec![],
         }
     }
 
@@ -61,17 +94,28 @@ impl GameBoardBuilder {
         }
     }
 
-    /// Push a piece to the board
+    /// Push a square that isn't part of the board
+    pub fn push_void(&mut self) {
+        self.voids.push((self.current_x, self.game_board.height - 1));
+        self.push_empty(1);
+    }
+
+    /// Push a piece to the board. Uppercase pieces are white and lowercase pieces are black
     pub fn push_piece(&mut self, symbol: String) {
-        self.current_x += 1;
         let col = if symbol == symbol.to_ascii_uppercase() {
             PieceColor::White
         } else {
             PieceColor::Black
         };
+        self.push_colored_piece(symbol, col);
+    }
+
+    /// Push a piece of any color to the board
+    pub fn push_colored_piece(&mut self, symbol: String, color: PieceColor) {
+        self.current_x += 1;
         self.game_board.board[self.game_board.height-1].push(Some(GamePiece{
             symbol: symbol.to_ascii_lowercase(),
-            color: col,
+            color,
             has_moved: false,
         }));
         if self.game_board.width < self.current_x {
@@ -88,38 +132,72 @@ struct FFenParser;
 impl GameBoard {
 
     /// Creates a `GameBoard` from a string with "Fairy Forsythe Edwards Notation" (ffen) syntax
-    /// (https://www.chessvariants.com/programs.dir/ffen2htm.htm)
+    /// (https://www.chessvariants.com/programs.dir/ffen2htm.htm). Pieces of players other than
+    /// white and black are prefixed with the id of their color in braces, like `{3}k`, and
+    /// squares that aren't part of the board are written as `-`
     pub fn from_ffen(ffen_raw_str: &str) -> Result<GameBoard, Error> {
-        let ffen_str = ffen_raw_str.replace('\n', "").replace(' ', "");
+        Ok(GameBoard::parse_ffen(ffen_raw_str)?.get_board())
+    }
+
+    /// Gets the number of squares written in each row of a ffen string. Rows shorter than the
+    /// widest row are filled with voids on the board
+    pub fn ffen_row_widths(ffen_raw_str: &str) -> Result<Vec<usize>, Error> {
+        Ok(GameBoard::parse_ffen(ffen_raw_str)?.game_board.board.iter().map(Vec::len).collect())
+    }
+
+    /// Parses a ffen string into a builder with the rows as they are written
+    fn parse_ffen(ffen_raw_str: &str) -> Result<GameBoardBuilder, Error> {
+        let ffen_str = ffen_raw_str.replace(['\n', ' '], "");
 
         let ffen = match FFenParser::parse(Rule::ffen, &ffen_str) {
             Ok(mut x) => x.next().unwrap(),
-            Err(_) => return Err(Error::FFenParse),
+            Err(e) => return Err(Error::FFenParse { input: ffen_str.clone(), position: parse_position(&e) }),
         };
 
         let mut board = GameBoardBuilder::new();
 
         use pest::iterators::Pair;
 
-        fn parse(pair: Pair<Rule>, board: &mut GameBoardBuilder) {
+        /// Fails with the character of a color that doesn't exist
+        fn parse(pair: Pair<Rule>, board: &mut GameBoardBuilder) -> Result<(), usize> {
             match pair.as_rule() {
                 Rule::line |
-                Rule::ffen => pair.into_inner().for_each(|p| parse(p, board)),
+                Rule::ffen => {
+                    for p in pair.into_inner() {
+                        parse(p, board)?;
+                    }
+                },
                 Rule::newline => {
                     board.next_line();
-                    parse(pair.into_inner().next().unwrap(), board)
+                    parse(pair.into_inner().next().unwrap(), board)?;
                 },
                 Rule::fpiece => {
                     let piece = pair.into_inner().next().unwrap();
-                    parse(piece, board);
+                    parse(piece, board)?;
+                }
+                Rule::colored => {
+                    let mut inner = pair.into_inner();
+                    let color = inner.next().unwrap();
+                    let color = color.as_str().parse().ok()
+                        .and_then(PieceColor::from_id)
+                        .ok_or(color.as_span().start() + 1)?;
+                    let mut piece = inner.next().unwrap();
+                    if piece.as_rule() == Rule::fpiece {
+                        piece = piece.into_inner().next().unwrap();
+                    }
+                    board.push_colored_piece(piece.as_str().to_string(), color);
                 }
                 Rule::long_piece | 
                 Rule::piece => board.push_piece(pair.as_str().to_string()),
                 Rule::empty => board.push_empty(pair.as_str().parse::<usize>().unwrap()),
+                Rule::void => board.push_void(),
+                Rule::EOI => (),
+                Rule::color => unreachable!(),
             }
+            Ok(())
         }
-        parse(ffen, &mut board);
-        Ok(board.get_board())
+        parse(ffen, &mut board).map_err(|position| Error::FFenParse { input: ffen_str.clone(), position })?;
+        Ok(board)
     }
 
     /// Get a new, empty GameBoard
@@ -128,9 +206,43 @@ impl GameBoard {
             width: 0,
             height: 1,
             board: vec![vec![]],
+            last_move: None,
+            teams: Teams::default(),
+            geometry: Geometry::Square,
+            wrap: Wrap::default(),
+            voids: vec![],
         }
     }
 
+    /// Checks if (`x`, `y`) isn't part of the board
+    pub fn is_void(&self, x: usize, y: usize) -> bool {
+        matches!(self.voids.get(y).and_then(|row| row.get(x)), Some(true))
+    }
+
+    /// Gets the coordinates of the squares that aren't part of the board, row by row
+    pub fn void_squares(&self) -> Vec<(usize, usize)> {
+        let mut voids = vec![];
+        for (y, row) in self.voids.iter().enumerate() {
+            for (x, void) in row.iter().enumerate() {
+                if *void {
+                    voids.push((x, y));
+                }
+            }
+        }
+        voids
+    }
+
+    /// Gets the total value of the pieces of a player. Royal pieces are left out, as they can't be
+    /// traded
+    pub fn material(&self, color: PieceColor, pieces: &PieceList) -> f32 {
+        self.board.iter()
+            .flatten()
+            .flatten()
+            .filter(|piece| piece.color == color && !pieces[&piece.symbol].kingstatus)
+            .map(|piece| pieces[&piece.symbol].value)
+            .sum()
+    }
+
     /// Gets a list of positions of pieces matching a predicate (`pred`)
     pub fn get_positions_of(&self, pred: &dyn Fn(&GamePiece) -> bool) -> Vec<(usize, usize)> {
         let mut pieces = vec![];
@@ -143,26 +255,44 @@ impl GameBoard {
                 }
             }
         }
-        return pieces;
+        pieces
     }
 
     /// Moves a piece from one place to another and takes another piece if the new location is
-    /// occupied by an opponents piece
+    /// occupied by an opponents piece. The piece is replaced if the move is a promotion, the
+    /// partner piece is moved as well when castling and pieces captured en passant are removed
     pub fn do_move(&mut self, mv: &GameMove) -> Result<bool, Error> {
         if mv.from.0 > self.width || mv.to.0 > self.width 
             || mv.from.1 > self.height || mv.to.1 > self.height {
             return Err(Error::InvalidMove);
         }
-        let take = if self.board[mv.to.1][mv.to.0].is_some() {
-            true
-        } else {false};
+        let take = self.board[mv.to.1][mv.to.0].is_some();
         self.board[mv.to.1][mv.to.0] = self.board[mv.from.1][mv.from.0].take();
         match &mut self.board[mv.to.1][mv.to.0] {
             // Making move
-            Some(x) => x.has_moved = true,
+            Some(x) => {
+                x.has_moved = true;
+                if let Some(symbol) = &mv.promotion {
+                    x.symbol = symbol.clone();
+                }
+            },
             None => return Err(Error::InvalidMove),
         }
         self.board[mv.from.1][mv.from.0] = None;
+        // Moving the partner piece when castling
+        if let Some((from, to)) = mv.castle {
+            self.board[to.1][to.0] = self.board[from.1][from.0].take();
+            match &mut self.board[to.1][to.0] {
+                Some(x) => x.has_moved = true,
+                None => return Err(Error::InvalidMove),
+            }
+        }
+        // Removing a piece captured en passant
+        let take = match mv.captures {
+            Some((x, y)) => self.board[y][x].take().is_some() || take,
+            None => take,
+        };
+        self.last_move = Some(mv.clone());
 
         Ok(take)
     }
@@ -179,11 +309,13 @@ impl fmt::Display for GameBoard {
                     } else {
                         write!(f, "{}|", piece.symbol)?;
                     }
+                } else if self.is_void(x, y) {
+                    write!(f, "-|")?;
                 } else {
                     write!(f, " |")?;
                 }
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
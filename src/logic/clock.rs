@@ -0,0 +1,149 @@
+use crate::{
+    error::Error,
+    logic::color::PieceColor,
+};
+use serde::{Serialize, Deserialize};
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// How much time the players have for their moves
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TimeControl {
+    /// Base time for the game, with `increment` added after each move
+    Increment {
+        base: Duration,
+        increment: Duration,
+    },
+    /// Base time for the game, where up to `delay` of the time used on a move is given back
+    Bronstein {
+        base: Duration,
+        delay: Duration,
+    },
+    /// A fixed amount of time for every move
+    PerMove(Duration),
+}
+
+impl FromStr for TimeControl {
+    type Err = Error;
+
+    /// Parses a time control in seconds written as `<base>+<increment>`, `<base>d<delay>` or
+    /// `<time>/move`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let secs = |x: &str| x.trim().parse::<f64>().ok()
+            .and_then(|x| Duration::try_from_secs_f64(x).ok())
+            .ok_or_else(|| Error::TimeControlParse(s.to_string()));
+        if let Some(time) = s.strip_suffix("/move") {
+            return Ok(TimeControl::PerMove(secs(time)?));
+        }
+        let parts: Vec<&str> = s.splitn(2, &['+', 'd'][..]).collect();
+        match parts.as_slice() {
+            [base] => Ok(TimeControl::Increment { base: secs(base)?, increment: Duration::default() }),
+            [base, extra] if s.contains('+') => Ok(TimeControl::Increment { base: secs(base)?, increment: secs(extra)? }),
+            [base, extra] => Ok(TimeControl::Bronstein { base: secs(base)?, delay: secs(extra)? }),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Display for TimeControl {
+    /// Writes the time control in the format it is parsed from
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeControl::Increment { base, increment } if *increment == Duration::default() =>
+                write!(f, "{}", base.as_secs_f64()),
+            TimeControl::Increment { base, increment } =>
+                write!(f, "{}+{}", base.as_secs_f64(), increment.as_secs_f64()),
+            TimeControl::Bronstein { base, delay } =>
+                write!(f, "{}d{}", base.as_secs_f64(), delay.as_secs_f64()),
+            TimeControl::PerMove(time) => write!(f, "{}/move", time.as_secs_f64()),
+        }
+    }
+}
+
+/// Keeps track of the time left for each player
+#[derive(Debug, Clone)]
+pub struct Clock {
+    /// Time control used by the clock
+    pub control: TimeControl,
+    /// Time left for each player at the start of their turn, indexed by color
+    remaining: Vec<Duration>,
+    /// The player whose clock is running and when their turn started
+    running: Option<(PieceColor, Instant)>,
+}
+
+impl Clock {
+    /// Creates a stopped clock for the given players
+    pub fn new(control: TimeControl, colors: &[PieceColor]) -> Clock {
+        let base = match control {
+            TimeControl::Increment { base, .. } | TimeControl::Bronstein { base, .. } => base,
+            TimeControl::PerMove(time) => time,
+        };
+        let size = colors.iter().map(|c| *c as usize + 1).max().unwrap_or(0);
+        Clock {
+            control,
+            remaining: vec![base; size],
+            running: None,
+        }
+    }
+
+    /// Starts the clock of a player
+    pub fn start_turn(&mut self, color: PieceColor, now: Instant) {
+        if let TimeControl::PerMove(time) = self.control {
+            self.remaining[color as usize] = time;
+        }
+        self.running = Some((color, now));
+    }
+
+    /// Stops the clock of the player that made a move and adds any increment or delay. Returns
+    /// false if the player ran out of time
+    pub fn end_turn(&mut self, now: Instant) -> bool {
+        let (color, start) = match self.running.take() {
+            Some(running) => running,
+            None => return true,
+        };
+        let used = now.saturating_duration_since(start);
+        let remaining = &mut self.remaining[color as usize];
+        if used >= *remaining {
+            *remaining = Duration::default();
+            return false;
+        }
+        *remaining -= used;
+        match self.control {
+            TimeControl::Increment { increment, .. } => *remaining += increment,
+            TimeControl::Bronstein { delay, .. } => *remaining += used.min(delay),
+            TimeControl::PerMove(_) => (),
+        }
+        true
+    }
+
+    /// Stops the clock without changing the time of any player
+    pub fn stop(&mut self, now: Instant) {
+        if let Some((color, start)) = self.running.take() {
+            let remaining = &mut self.remaining[color as usize];
+            *remaining = remaining.checked_sub(now.saturating_duration_since(start)).unwrap_or_default();
+        }
+    }
+
+    /// Gets the time left for a player
+    pub fn remaining(&self, color: PieceColor, now: Instant) -> Duration {
+        let remaining = self.remaining[color as usize];
+        match self.running {
+            Some((running, start)) if running == color =>
+                remaining.checked_sub(now.saturating_duration_since(start)).unwrap_or_default(),
+            _ => remaining,
+        }
+    }
+
+    /// Sets the time left for a player, used when restoring a saved game
+    pub fn set_remaining(&mut self, color: PieceColor, time: Duration) {
+        self.remaining[color as usize] = time;
+    }
+
+    /// Gets the time when the running clock runs out
+    pub fn deadline(&self) -> Option<Instant> {
+        self.running.map(|(color, start)| start + self.remaining[color as usize])
+    }
+}
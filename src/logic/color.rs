@@ -1,18 +1,98 @@
+use serde::{Serialize, Deserialize};
+use std::str::FromStr;
+use crate::error::Error;
+
 /// Color of a players pieces
 #[repr(u8)]
-#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum PieceColor {
     White = 0,
     Black = 1,
     Yellow = 2,
+    Red = 3,
+    Blue = 4,
+    Green = 5,
 }
 
-impl From<i64> for PieceColor {
-    fn from(orig: i64) -> PieceColor {
-        match orig {
-            0 => PieceColor::Black,
-            1 => PieceColor::White,
-            _ => PieceColor::Yellow,
+impl PieceColor {
+    /// Number of colors
+    pub const COUNT: usize = 6;
+
+    /// Gets the color with the id used when talking to clients
+    pub fn from_id(id: u8) -> Option<PieceColor> {
+        match id {
+            0 => Some(PieceColor::White),
+            1 => Some(PieceColor::Black),
+            2 => Some(PieceColor::Yellow),
+            3 => Some(PieceColor::Red),
+            4 => Some(PieceColor::Blue),
+            5 => Some(PieceColor::Green),
+            _ => None,
+        }
+    }
+
+    /// Gets the step forward for pieces of the color. White and red start at the bottom of the
+    /// board, black and yellow at the top, blue on the left and green on the right
+    pub fn forward(&self) -> (i32, i32) {
+        match self {
+            PieceColor::White | PieceColor::Red => (0, -1),
+            PieceColor::Black | PieceColor::Yellow => (0, 1),
+            PieceColor::Blue => (1, 0),
+            PieceColor::Green => (-1, 0),
         }
     }
 }
+
+impl FromStr for PieceColor {
+    type Err = Error;
+
+    /// Parses the name or id of a color
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "white" => Ok(PieceColor::White),
+            "black" => Ok(PieceColor::Black),
+            "yellow" => Ok(PieceColor::Yellow),
+            "red" => Ok(PieceColor::Red),
+            "blue" => Ok(PieceColor::Blue),
+            "green" => Ok(PieceColor::Green),
+            id => id.parse().ok().and_then(PieceColor::from_id).ok_or_else(|| Error::ColorParse(s.to_string())),
+        }
+    }
+}
+
+/// Team of each color, indexed by color id. Colors on the same team are allies, which can't
+/// capture or check each other
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Teams([u8; PieceColor::COUNT]);
+
+impl Default for Teams {
+    /// Every color plays on its own
+    fn default() -> Self {
+        let mut teams = [0; PieceColor::COUNT];
+        for (id, team) in teams.iter_mut().enumerate() {
+            *team = id as u8;
+        }
+        Teams(teams)
+    }
+}
+
+impl Teams {
+    /// Creates teams from groups of allied colors. Colors that aren't in any group play on their
+    /// own
+    pub fn new(groups: &[Vec<PieceColor>]) -> Teams {
+        let mut teams = Teams::default();
+        for group in groups {
+            if let Some(first) = group.first() {
+                for color in group {
+                    teams.0[*color as usize] = *first as u8;
+                }
+            }
+        }
+        teams
+    }
+
+    /// Checks if two colors are on the same team. Every color is allied with itself
+    pub fn allied(&self, a: PieceColor, b: PieceColor) -> bool {
+        self.0[a as usize] == self.0[b as usize]
+    }
+}
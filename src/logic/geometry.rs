@@ -0,0 +1,45 @@
+use serde::{Serialize, Deserialize};
+use std::str::FromStr;
+use crate::error::Error;
+
+/// Shape of the cells of a board and how they connect
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Geometry {
+    /// Square cells in rows and columns
+    #[default]
+    Square,
+    /// Flat-topped hexagons in axial coordinates. Columns are the files of the board, and each
+    /// row slants one hexagon down for every column to the right. Orthogonal steps go to the six
+    /// neighbouring hexagons and diagonal steps go between two of them
+    Hex,
+}
+
+impl FromStr for Geometry {
+    type Err = Error;
+
+    /// Parses the name of a geometry, either "square" or "hex"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "square" => Ok(Geometry::Square),
+            "hex" | "hexagonal" => Ok(Geometry::Hex),
+            _ => Err(Error::GeometryParse(s.to_string())),
+        }
+    }
+}
+
+/// Edges of a board that pieces can move across, coming back on the opposite side
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Wrap {
+    /// The left and right edges are joined, like on a cylinder
+    pub horizontal: bool,
+    /// The top and bottom edges are joined. Wrapping both ways makes the board a torus
+    pub vertical: bool,
+}
+
+impl Wrap {
+    /// Checks if the board wraps in any direction
+    pub fn any(&self) -> bool {
+        self.horizontal || self.vertical
+    }
+}
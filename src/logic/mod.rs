@@ -2,10 +2,27 @@
 pub mod piece;
 /// A representation of a rectangular chessboard of any size
 pub mod board;
+/// Shapes of boards
+pub mod geometry;
 /// Descriptions for how a piece can move
 pub mod movement;
+/// Betza notation for how a piece can move
+pub mod betza;
 /// Movement generation from movement descriptors
 pub mod move_gen;
+/// Bitboard representation of a board used to check the legality of moves
+pub mod bitboard;
 pub mod color;
+/// Results of a game and detection of draws
+pub mod result;
+/// Time controls and chess clocks
+pub mod clock;
+/// Game records in a PGN-like format
+pub mod record;
+/// Alpha-beta search used by bots
+pub mod search;
+/// Counts reachable positions to test move generation
+pub mod perft;
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::needless_return)]
 mod test;
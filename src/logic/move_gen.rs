@@ -1,14 +1,27 @@
 use crate::logic::board::GameBoard;
-use crate::logic::piece::{GamePiece, PieceList};
+use crate::logic::bitboard::{Bitboard, Position, Wide};
+use crate::logic::piece::{GamePiece, Piece, PieceList, Promotion, Castling};
 use crate::logic::movement::*;
 use crate::logic::color::PieceColor;
+use crate::logic::geometry::{Geometry, Wrap};
+use crate::{Error, rules};
+use std::cell::RefCell;
 use serde::{Serialize, Deserialize};
-use rhai::{Engine, AST, Scope, Dynamic, serde::from_dynamic};
+use rhai::{Engine, AST, Scope, serde::from_dynamic};
 
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, PartialOrd, Ord)]
 pub struct GameMove {
     pub from: (usize, usize),
     pub to: (usize, usize),
+    /// Symbol of the piece the moved piece is promoted to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub promotion: Option<String>,
+    /// Start and end position of the partner piece when castling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub castle: Option<((usize, usize), (usize, usize))>,
+    /// Position of a piece captured without moving to its position, as when capturing en passant
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub captures: Option<(usize, usize)>,
 }
 
 impl GameMove {
@@ -16,6 +29,35 @@ impl GameMove {
         GameMove {
             from: (fx,fy),
             to: (tx,ty),
+            promotion: None,
+            castle: None,
+            captures: None,
+        }
+    }
+
+    /// Checks if a move given by a client describes this move. Fields that the server can
+    /// derive from the position, like the partner of a castling move, are ignored
+    pub fn matches(&self, other: &GameMove) -> bool {
+        self.from == other.from && self.to == other.to && self.promotion == other.promotion
+    }
+}
+
+/// Rhai rules used to generate extra moves
+#[derive(Clone, Copy)]
+pub struct RhaiEnv<'a> {
+    pub ast: &'a AST,
+    pub engine: &'a Engine,
+    /// Collects the errors of failing callbacks. Errors are only logged if it isn't given
+    pub errors: Option<&'a RefCell<Vec<Error>>>,
+}
+
+impl RhaiEnv<'_> {
+
+    /// Logs the error of a failing callback and collects it, if errors are collected
+    pub fn report(&self, error: Error) {
+        error!("{}", error);
+        if let Some(errors) = self.errors {
+            errors.borrow_mut().push(error);
         }
     }
 }
@@ -23,37 +65,212 @@ impl GameMove {
 pub fn generate_moves(color: PieceColor,
                       pieces: &PieceList,
                       board: &GameBoard,
-                      rhai_env: Option<(&AST, &Engine)>,
+                      rhai_env: Option<RhaiEnv>,
                      ) -> Vec<GameMove> {
     let mut moves: Vec<GameMove> = vec![];
     for y in 0..board.height {
         for x in 0..board.width {
             if let Some(piece) = &board.board[y][x] {
                 if piece.color == color {
-                    gen_moves_for_piece(&color,&x,&y,&piece,pieces,board, &mut moves);
+                    let first_move = moves.len();
+                    gen_moves_for_piece(&color,&x,&y,piece,pieces,board, &mut moves);
                     let piece_type = pieces.get(&piece.symbol).unwrap();
+                    if !piece_type.castling.is_empty() && !piece.has_moved {
+                        gen_castling_moves(&color, x, y, &piece_type.castling, pieces, board, &mut moves);
+                    }
+                    if piece_type.en_passant {
+                        gen_en_passant_moves(&color, x, y, piece, pieces, board, &mut moves);
+                    }
                     // Adding extra moves from rhai
-                    if let Some((ast, engine)) = rhai_env {
+                    if let Some(env) = rhai_env {
                         let mut scope = Scope::new();
                         if let Some(extra_moves) = &piece_type.extra_moves {
-                            let e: Vec<Dynamic> =
-                                engine.call_fn(&mut scope, ast, &extra_moves, (x, y))
-                                .unwrap();
-                            e.iter()
-                                .map(|m| {
-                                    let a: [i64; 2] = from_dynamic(m).unwrap();
-                                    GameMove::new(x, y, a[0] as usize, a[1] as usize)
-                                })
-                                .for_each(|m| moves.push(m));
+                            // A failing callback is reported and adds no moves
+                            let targets = rules::call_fn(env.engine, &mut scope, env.ast, &extra_moves.name, (x, y))
+                                .and_then(|targets| from_dynamic::<Vec<[i64; 2]>>(&targets));
+                            match targets {
+                                Ok(targets) => targets.iter()
+                                    .map(|a| GameMove::new(x, y, a[0] as usize, a[1] as usize))
+                                    .filter(|m| !board.is_void(m.to.0, m.to.1))
+                                    .for_each(|m| moves.push(m)),
+                                Err(error) => env.report(Error::Callback {
+                                    callback: "add_moves",
+                                    piece: piece.symbol.clone(),
+                                    line: extra_moves.line,
+                                    error,
+                                }),
+                            }
                         }
                     }
+                    if let Some(promotion) = &piece_type.promotion {
+                        let piece_moves = moves.split_off(first_move);
+                        add_promotions(&color, promotion, board, piece_moves, &mut moves);
+                    }
                 }
             }
         }
     }
     moves.sort();
     moves.dedup();
-    return moves;
+    moves
+}
+
+/// Generates castling moves for the unmoved piece at (`x`, `y`). The piece moves `distance`
+/// squares towards an unmoved partner on the same rank, with only empty squares between them,
+/// and the partner lands on the square the piece passed over. The piece cannot castle out of,
+/// through or into check
+fn gen_castling_moves(color: &PieceColor, x: usize, y: usize, castling: &[Castling], pieces: &PieceList, board: &GameBoard, moves: &mut Vec<GameMove>) {
+    // Ranks run across the forward direction of the player
+    let axis = if color.forward().0 == 0 { (1, 0) } else { (0, 1) };
+    let square = |step: i32| {
+        let sx = x as i32 + axis.0 * step;
+        let sy = y as i32 + axis.1 * step;
+        if sx < 0 || sy < 0 || sx as usize >= board.width || sy as usize >= board.height
+            || board.is_void(sx as usize, sy as usize) {
+            None
+        } else {
+            Some((sx as usize, sy as usize))
+        }
+    };
+    for rule in castling {
+        for dir in &[-1i32, 1] {
+            // Finding the first piece in the direction
+            let mut step = *dir;
+            while matches!(square(step), Some((sx, sy)) if board.board[sy][sx].is_none()) {
+                step += dir;
+            }
+            let (px, py) = match square(step) {
+                Some(pos) if step.unsigned_abs() as usize > rule.distance => pos,
+                _ => continue,
+            };
+            let partner = board.board[py][px].as_ref().unwrap();
+            if partner.color != *color || partner.symbol != rule.partner || partner.has_moved {
+                continue;
+            }
+            let safe = (0..=rule.distance as i32).all(|step| {
+                let (sx, sy) = square(dir * step).unwrap();
+                let mut nboard = board.clone();
+                nboard.board[sy][sx] = nboard.board[y][x].take();
+                !is_checked(*color, &sx, &sy, pieces, &nboard)
+            });
+            if safe {
+                let (tx, ty) = square(dir * rule.distance as i32).unwrap();
+                moves.push(GameMove {
+                    castle: Some(((px, py), square(dir * (rule.distance as i32 - 1)).unwrap())),
+                    ..GameMove::new(x, y, tx, ty)
+                });
+            }
+        }
+    }
+}
+
+/// Generates en passant captures for the piece at (`x`, `y`). If the last move was a straight
+/// move of more than one square by an opponents en passant piece, the piece can capture it by
+/// moving to one of the squares it passed, as if it had only moved to that square
+fn gen_en_passant_moves(color: &PieceColor, x: usize, y: usize, piece: &GamePiece, pieces: &PieceList, board: &GameBoard, moves: &mut Vec<GameMove>) {
+    let (last_move, target) = match en_passant_move(board, pieces) {
+        Some((last_move, target)) if !board.teams.allied(target.color, *color) => (last_move, target),
+        _ => return,
+    };
+    let (lx, ly) = last_move.to;
+    let dx = lx as i32 - last_move.from.0 as i32;
+    let dy = ly as i32 - last_move.from.1 as i32;
+    let mut normal_moves = vec![];
+    gen_moves_for_piece(color, &x, &y, piece, pieces, board, &mut normal_moves);
+    for step in 1..dx.abs().max(dy.abs()) {
+        let px = (last_move.from.0 as i32 + dx.signum() * step) as usize;
+        let py = (last_move.from.1 as i32 + dy.signum() * step) as usize;
+        if board.board[py][px].is_some() || normal_moves.iter().any(|m| m.to == (px, py)) {
+            continue;
+        }
+        // Placing the passing piece on the square to find captures of it
+        let mut nboard = board.clone();
+        nboard.board[py][px] = Some(target.clone());
+        let mut captures = vec![];
+        gen_moves_for_piece(color, &x, &y, piece, pieces, &nboard, &mut captures);
+        if captures.iter().any(|m| m.to == (px, py)) {
+            moves.push(GameMove {
+                captures: Some((lx, ly)),
+                ..GameMove::new(x, y, px, py)
+            });
+        }
+    }
+}
+
+/// Gets the last move and the piece that made it, if it was a straight move of more than one
+/// square by an en passant piece that can be captured on the squares it passed
+pub fn en_passant_move<'a>(board: &'a GameBoard, pieces: &PieceList) -> Option<(&'a GameMove, &'a GamePiece)> {
+    let last_move = board.last_move.as_ref()?;
+    let (lx, ly) = last_move.to;
+    let piece = board.board[ly][lx].as_ref().filter(|piece| pieces[&piece.symbol].en_passant)?;
+    let dx = lx as i32 - last_move.from.0 as i32;
+    let dy = ly as i32 - last_move.from.1 as i32;
+    if !(dx == 0 || dy == 0 || dx.abs() == dy.abs()) || dx.abs().max(dy.abs()) < 2 {
+        return None;
+    }
+    Some((last_move, piece))
+}
+
+/// Adds the moves of a single piece to `moves`, where moves ending on a promotion rank are
+/// replaced with a move for each piece it can promote to
+fn add_promotions(color: &PieceColor, promotion: &Promotion, board: &GameBoard, piece_moves: Vec<GameMove>, moves: &mut Vec<GameMove>) {
+    for mov in piece_moves {
+        if promotion.on_rank(*color, mov.to, board) {
+            for symbol in &promotion.pieces {
+                moves.push(GameMove {
+                    promotion: Some(symbol.clone()),
+                    ..mov.clone()
+                });
+            }
+        } else {
+            moves.push(mov);
+        }
+    }
+}
+
+/// Read access to the squares of a board, so moves can be walked on any board representation
+pub trait Squares {
+    /// Width and height of the board
+    fn size(&self) -> (usize, usize);
+    /// Gets the color of the piece at (`x`, `y`), if there is one
+    fn color_at(&self, x: usize, y: usize) -> Option<PieceColor>;
+    fn has_moved(&self, x: usize, y: usize) -> bool;
+    /// Checks if pieces of two colors are allies
+    fn allied(&self, a: PieceColor, b: PieceColor) -> bool;
+    /// Checks if (`x`, `y`) isn't part of the board
+    fn is_void(&self, x: usize, y: usize) -> bool;
+    fn geometry(&self) -> Geometry;
+    fn wrap(&self) -> Wrap;
+}
+
+impl Squares for GameBoard {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn color_at(&self, x: usize, y: usize) -> Option<PieceColor> {
+        self.board[y][x].as_ref().map(|piece| piece.color)
+    }
+
+    fn has_moved(&self, x: usize, y: usize) -> bool {
+        matches!(&self.board[y][x], Some(piece) if piece.has_moved)
+    }
+
+    fn allied(&self, a: PieceColor, b: PieceColor) -> bool {
+        self.teams.allied(a, b)
+    }
+
+    fn is_void(&self, x: usize, y: usize) -> bool {
+        GameBoard::is_void(self, x, y)
+    }
+
+    fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    fn wrap(&self) -> Wrap {
+        self.wrap
+    }
 }
 
 pub fn gen_moves_for_piece( color: &PieceColor,
@@ -64,126 +281,301 @@ pub fn gen_moves_for_piece( color: &PieceColor,
                             board: &GameBoard, 
                             moves: &mut Vec<GameMove>
                            ) {
-    let movement_rules = &pieces[&piece.symbol].moves;
+    piece_targets(*color, *x, *y, &pieces[&piece.symbol], board, &mut |tx, ty| {
+        moves.push(GameMove::new(*x, *y, tx, ty));
+    });
+}
+
+/// Calls `visit` with every square the piece at (`x`, `y`) can move to by its movement rules.
+/// A square is visited once for each way the piece can reach it
+pub fn piece_targets(color: PieceColor, x: usize, y: usize, piece: &Piece, board: &dyn Squares, visit: &mut dyn FnMut(usize, usize)) {
+    let walker = Walker { color, board, attacks: false };
+    for move_rule in &piece.moves {
+        walker.rule_targets(x, y, move_rule, visit);
+    }
+}
+
+/// Calls `visit` with every square the piece at (`x`, `y`) attacks, which are the squares it could
+/// capture an opponents piece on. Squares with pieces of its own color aren't attacked
+pub fn piece_attacks(color: PieceColor, x: usize, y: usize, piece: &Piece, board: &dyn Squares, visit: &mut dyn FnMut(usize, usize)) {
+    let walker = Walker { color, board, attacks: true };
+    for move_rule in &piece.moves {
+        walker.rule_targets(x, y, move_rule, visit);
+    }
+}
 
-    for move_rule in movement_rules {
+/// Checks if the piece at (`x`, `y`) can move to `target`. Only the directions pointing at the
+/// target are walked, unless the rule continues with a `then` rule
+pub fn piece_reaches(color: PieceColor, x: usize, y: usize, piece: &Piece, board: &dyn Squares, target: (usize, usize)) -> bool {
+    let walker = Walker { color, board, attacks: false };
+    let dx = target.0 as i32 - x as i32;
+    let dy = target.1 as i32 - y as i32;
+    let mut reached = false;
+    for move_rule in &piece.moves {
         for direction in &move_rule.direction {
-            let mut dir_moves = gen_moves_from_dir(color, x, y, &move_rule, direction.get_coords(color, &move_rule.distance), board);
-            moves.append(&mut dir_moves);
+            let coords;
+            let dirs = match direction.steps(&color, board.geometry()) {
+                Some(steps) => steps,
+                None => {
+                    coords = direction.get_coords(&color, &move_rule.distance, board.geometry());
+                    &coords
+                },
+            };
+            // Pieces can reach squares behind them by moving across the edge of a wrapped board
+            let wraps = board.wrap().any();
+            for dir in dirs.iter().filter(|dir| wraps || move_rule.then.is_some() || points_at(**dir, dx, dy)) {
+                walker.targets_from_dir(x, y, move_rule, &[*dir], &mut |tx, ty| reached |= (tx, ty) == target);
+                if reached {
+                    return true;
+                }
+            }
         }
     }
+    false
 }
-pub fn gen_moves_for_piece_with_rule( color: &PieceColor,
-                            x: &usize, 
-                            y: &usize,
-                            move_rule: &Movement,
-                            board: &GameBoard, 
-                            moves: &mut Vec<GameMove>) {
-
-    for direction in &move_rule.direction {
-        let mut dir_moves = gen_moves_from_dir(color, x, y, &move_rule, direction.get_coords(color, &move_rule.distance), board);
-        moves.append(&mut dir_moves);
-    }
-}
-
-
-fn gen_moves_from_dir(color: &PieceColor, x: &usize, y: &usize, move_rule: &Movement, dirs: Vec<(i32, i32)>, board: &GameBoard)-> Vec<GameMove> {
-    if move_rule.initial && board.board[*y][*x].as_ref().unwrap().has_moved {
-        return vec![];
-    }
-    let mut moves = vec![];
-    for dir in dirs {
-        let mut mx = *x;
-        let mut my = *y;
-        let mut d_moved = 0;
-        let mut has_leaped = false;
-        'inner: loop {
-            d_moved += 1;
-            mx = (mx as i32 + dir.0) as usize;
-            my = (my as i32 + dir.1) as usize;
-            if mx < board.width && my < board.height {
-                if move_rule.distance.correct(&d_moved) {
-                    moves.push(GameMove::new(*x,*y,mx,my));
-                    if let Some(end_piece) = &board.board[my][mx] {
-                        if move_rule.nocapture || end_piece.color == *color || (move_rule.locust && !has_leaped) {
-                            moves.pop();
-                        }
-                        if !move_rule.leaper && (!move_rule.locust || has_leaped) { 
-                            break 'inner;
+
+/// Checks if a square `dx`, `dy` squares away can be reached by repeating a step of `dir`
+fn points_at(dir: (i32, i32), dx: i32, dy: i32) -> bool {
+    let steps = if dir.0 != 0 {
+        dx / dir.0
+    } else if dir.1 != 0 {
+        dy / dir.1
+    } else {
+        return false;
+    };
+    steps > 0 && dir.0 * steps == dx && dir.1 * steps == dy
+}
+
+/// Moves a coordinate `delta` squares along an axis of `size` squares. Coordinates that leave
+/// the board come back on the other side if the axis wraps, and are out of bounds otherwise
+fn step(pos: usize, delta: i32, size: usize, wraps: bool) -> usize {
+    let pos = pos as i32 + delta;
+    if wraps {
+        pos.rem_euclid(size as i32) as usize
+    } else {
+        pos as usize
+    }
+}
+
+/// Walks the movement rules of a piece of `color` on a board
+struct Walker<'a> {
+    color: PieceColor,
+    board: &'a dyn Squares,
+    /// Visit empty squares the piece could capture on, instead of the squares it can move to
+    attacks: bool,
+}
+
+impl Walker<'_> {
+
+    fn rule_targets(&self, x: usize, y: usize, move_rule: &Movement, visit: &mut dyn FnMut(usize, usize)) {
+        for direction in &move_rule.direction {
+            let geometry = self.board.geometry();
+            match direction.steps(&self.color, geometry) {
+                Some(steps) => self.targets_from_dir(x, y, move_rule, steps, visit),
+                None => self.targets_from_dir(x, y, move_rule, &direction.get_coords(&self.color, &move_rule.distance, geometry), visit),
+            }
+        }
+    }
+
+    fn targets_from_dir(&self, x: usize, y: usize, move_rule: &Movement, dirs: &[(i32, i32)], visit: &mut dyn FnMut(usize, usize)) {
+        if move_rule.initial && self.board.has_moved(x, y) {
+            return;
+        }
+        let (width, height) = self.board.size();
+        let wrap = self.board.wrap();
+        for dir in dirs {
+            let mut mx = x;
+            let mut my = y;
+            let mut d_moved = 0;
+            let mut has_leaped = false;
+            'inner: loop {
+                d_moved += 1;
+                if move_rule.lame && self.leap_blocked(mx, my, *dir) {
+                    break 'inner;
+                }
+                mx = step(mx, dir.0, width, wrap.horizontal);
+                my = step(my, dir.1, height, wrap.vertical);
+                // Moves around a wrapped board end when they come back to the start
+                if (mx, my) == (x, y) {
+                    break 'inner;
+                }
+                // Voids stop the piece like the edge of the board
+                if mx < width && my < height && !self.board.is_void(mx, my) {
+                    let end_color = self.board.color_at(mx, my);
+                    if move_rule.hopper {
+                        match end_color {
+                            // Looking for a piece to hop over
+                            _ if !has_leaped => has_leaped = end_color.is_some(),
+                            Some(end_color) => {
+                                let blocked = move_rule.nocapture || self.board.allied(end_color, self.color);
+                                if !blocked && move_rule.reaches(d_moved) {
+                                    self.visit_target(mx, my, move_rule, visit);
+                                }
+                                break 'inner;
+                            },
+                            None => {
+                                let blocked = if self.attacks { move_rule.nocapture } else { move_rule.capture };
+                                if !blocked && move_rule.reaches(d_moved) {
+                                    self.visit_target(mx, my, move_rule, visit);
+                                }
+                                if move_rule.grasshopper {
+                                    break 'inner;
+                                }
+                            },
                         }
-                        if move_rule.nocapture || end_piece.color == *color || (move_rule.locust && !has_leaped) {
-                            has_leaped = true;
+                    } else if move_rule.reaches(d_moved) {
+                        if let Some(end_color) = end_color {
+                            let blocked = move_rule.nocapture || self.board.allied(end_color, self.color) || (move_rule.locust && !has_leaped);
+                            if !blocked {
+                                self.visit_target(mx, my, move_rule, visit);
+                            }
+                            if !move_rule.passes_pieces() && (!move_rule.locust || has_leaped) { 
+                                break 'inner;
+                            }
+                            if blocked {
+                                has_leaped = true;
+                            }
+                        } else if self.attacks {
+                            if !(move_rule.nocapture || (move_rule.locust && !has_leaped)) {
+                                self.visit_target(mx, my, move_rule, visit);
+                            }
+                        } else if !(move_rule.capture || (move_rule.locust && has_leaped)) {
+                            self.visit_target(mx, my, move_rule, visit);
                         }
-                        
-                    }
-                    else if move_rule.capture || (move_rule.locust && has_leaped){
-                        moves.pop();
+                    } else if end_color.is_some() && !move_rule.passes_pieces() && (!move_rule.locust || has_leaped) {
+                        break 'inner;
                     }
                 } else {
-                    if let Some(_) = &board.board[my][mx] {
-                        if !move_rule.leaper && (!move_rule.locust || has_leaped) {
-                            break 'inner;
-                        }
-                    }
+                    break 'inner;
                 }
+            }
+        }
+    }
+
+    /// Checks if a lame leap by `dir` from (`x`, `y`) is blocked. The leap goes straight along
+    /// its longer axis first and then diagonally, and every square on the way has to be empty
+    fn leap_blocked(&self, x: usize, y: usize, dir: (i32, i32)) -> bool {
+        let (width, height) = self.board.size();
+        let wrap = self.board.wrap();
+        let (ax, ay) = (dir.0.abs(), dir.1.abs());
+        let straight = (ax - ay).abs();
+        for i in 1..ax.max(ay) {
+            let diagonal = (i - straight).max(0);
+            let (ox, oy) = if ax > ay {
+                (dir.0.signum() * i, dir.1.signum() * diagonal)
             } else {
-                break 'inner;
+                (dir.0.signum() * diagonal, dir.1.signum() * i)
+            };
+            let px = step(x, ox, width, wrap.horizontal);
+            let py = step(y, oy, height, wrap.vertical);
+            if px >= width || py >= height || self.board.is_void(px, py) || self.board.color_at(px, py).is_some() {
+                return true;
             }
         }
+        false
     }
-    if let Some(then) = &move_rule.then {
-        let mut true_moves = vec![];
-        for mov in moves {
-            true_moves.push(mov.clone());
-            gen_moves_for_piece_with_rule(color, &mov.to.0, &mov.to.1, then, board, &mut true_moves);
+
+    /// Visits a square a movement rule ends on, and the squares its `then` rule continues to
+    fn visit_target(&self, x: usize, y: usize, move_rule: &Movement, visit: &mut dyn FnMut(usize, usize)) {
+        visit(x, y);
+        if let Some(then) = &move_rule.then {
+            self.rule_targets(x, y, then, visit);
         }
-        return true_moves.into_iter().map(|mut t| {t.from = (*x,*y); t}).collect();
     }
-    return moves;
 }
 
-pub fn is_checked(p_color: PieceColor, dx: &usize, dy: &usize, pieces: &PieceList, board: &GameBoard) -> bool {
-    let mut moves: Vec<GameMove> = vec![];
-    let color = match p_color {
-        PieceColor::White => PieceColor::Black,
-        _ => PieceColor::White
-    };
-    for y in 0..board.height {
-        for x in 0..board.width {
-            if let Some(piece) = &board.board[y][x] {
-                if piece.color == color {
-                    gen_moves_for_piece(&color,&x,&y,&piece,pieces,board, &mut moves);
-                    for mov in &moves {
-                        if mov.to.0 == *dx && mov.to.1 == *dy {
-                            return true;
-                        }
-                    }
-                    moves.clear();
-                }
+/// Generates the moves of a player that don't leave any of its royal pieces in check. Boards of
+/// up to 16x16 squares are checked on bitboards
+pub fn legal_moves(color: PieceColor,
+                   pieces: &PieceList,
+                   board: &GameBoard,
+                   rhai_env: Option<RhaiEnv>,
+                  ) -> Vec<GameMove> {
+    let moves = generate_moves(color, pieces, board, rhai_env);
+    if let Some(position) = Position::<u64>::from_board(board, pieces) {
+        moves.into_iter().filter(|mov| is_legal_on(&position, mov, color, pieces)).collect()
+    } else if let Some(position) = Position::<Wide>::from_board(board, pieces) {
+        moves.into_iter().filter(|mov| is_legal_on(&position, mov, color, pieces)).collect()
+    } else {
+        moves.into_iter().filter(|mov| is_legal(mov, color, pieces, board)).collect()
+    }
+}
+
+/// Checks if a move doesn't leave any royal piece of the moving player in check
+pub fn is_legal(mov: &GameMove, color: PieceColor, pieces: &PieceList, board: &GameBoard) -> bool {
+    if let Some(position) = Position::<u64>::from_board(board, pieces) {
+        return is_legal_on(&position, mov, color, pieces);
+    }
+    if let Some(position) = Position::<Wide>::from_board(board, pieces) {
+        return is_legal_on(&position, mov, color, pieces);
+    }
+    let mut nboard = board.clone();
+    if nboard.do_move(mov).is_err() {
+        return false;
+    }
+    !in_check(color, pieces, &nboard)
+}
+
+/// Checks if any royal piece of a player is attacked
+pub fn in_check(color: PieceColor, pieces: &PieceList, board: &GameBoard) -> bool {
+    if let Some(position) = Position::<u64>::from_board(board, pieces) {
+        return position.in_check(color);
+    }
+    if let Some(position) = Position::<Wide>::from_board(board, pieces) {
+        return position.in_check(color);
+    }
+    let attacked = attack_map(&|c| !board.teams.allied(c, color), pieces, board);
+    board.get_positions_of(&|piece: &GamePiece| piece.color == color && pieces[&piece.symbol].kingstatus)
+        .iter()
+        .any(|(x, y)| attacked[*y][*x])
+}
+
+/// Gets the squares attacked by the pieces of a player, sorted row by row
+pub fn attacked_squares(color: PieceColor, pieces: &PieceList, board: &GameBoard) -> Vec<(usize, usize)> {
+    if let Some(position) = Position::<u64>::from_board(board, pieces) {
+        return position.attacked_squares(color);
+    }
+    if let Some(position) = Position::<Wide>::from_board(board, pieces) {
+        return position.attacked_squares(color);
+    }
+    let attacked = attack_map(&|c| c == color, pieces, board);
+    let mut squares = vec![];
+    for (y, row) in attacked.iter().enumerate() {
+        for (x, square) in row.iter().enumerate() {
+            if *square {
+                squares.push((x, y));
             }
         }
     }
-    false
+    squares
+}
+
+/// Marks the squares attacked by the pieces of the players matching `attacker`, for boards too
+/// large for bitboards
+fn attack_map(attacker: &dyn Fn(PieceColor) -> bool, pieces: &PieceList, board: &GameBoard) -> Vec<Vec<bool>> {
+    let mut attacked = vec![vec![false; board.width]; board.height];
+    for (x, y) in board.get_positions_of(&|piece: &GamePiece| attacker(piece.color)) {
+        let piece = board.board[y][x].as_ref().unwrap();
+        piece_attacks(piece.color, x, y, &pieces[&piece.symbol], board, &mut |tx, ty| attacked[ty][tx] = true);
+    }
+    attacked
+}
+
+fn is_legal_on<'a, B: Bitboard>(position: &Position<'a, B>, mov: &GameMove, color: PieceColor, pieces: &'a PieceList) -> bool {
+    let mut next = position.clone();
+    next.do_move(mov, pieces) && !next.in_check(color)
+}
+
+pub fn is_checked(p_color: PieceColor, dx: &usize, dy: &usize, pieces: &PieceList, board: &GameBoard) -> bool {
+    !get_checkers(p_color, dx, dy, pieces, board).is_empty()
 }
 pub fn get_checkers(p_color: PieceColor, dx: &usize, dy: &usize, pieces: &PieceList, board: &GameBoard) -> Vec<(usize,usize)> {
-    let mut moves: Vec<GameMove> = vec![];
     let mut checkers: Vec<(usize,usize)> = vec![];
-    let color = match p_color {
-        PieceColor::White => PieceColor::Black,
-        _ => PieceColor::White
-    };
     for y in 0..board.height {
         for x in 0..board.width {
             if let Some(piece) = &board.board[y][x] {
-                if piece.color == color {
-                    gen_moves_for_piece(&color,&x,&y,&piece,pieces,board, &mut moves);
-                    'piece: for mov in &moves {
-                        if mov.to.0 == *dx && mov.to.1 == *dy {
-                            checkers.push((mov.from.0, mov.from.1));
-                            break 'piece;
-                        }
-                    }
-                    moves.clear();
+                if !board.teams.allied(piece.color, p_color) && piece_reaches(piece.color, x, y, &pieces[&piece.symbol], board, (*dx, *dy)) {
+                    checkers.push((x, y));
                 }
             }
         }
@@ -1,6 +1,7 @@
 use pest::Parser;
-use crate::error::Error;
+use crate::error::{Error, parse_position};
 use crate::logic::color::PieceColor;
+use crate::logic::geometry::Geometry;
 
 /// A struct describing a movement rule for a piece
 #[derive(Debug, Clone)]
@@ -14,6 +15,12 @@ pub struct Movement {
     pub nocapture: bool,
     pub leaper: bool,
     pub locust: bool,
+    /// Leaps are blocked by pieces on the squares they pass
+    pub lame: bool,
+    /// The piece has to hop over a piece, and can land anywhere behind it
+    pub hopper: bool,
+    /// Hoppers land on the square right behind the piece they hop over
+    pub grasshopper: bool,
 }
 
 /// Enum describing the different direction a piece can move.
@@ -28,7 +35,9 @@ pub enum Direction {
     DiagonalForward,
     DiagonalBackward,
     Hippogonal,
-    Movement(Box<Movement>)
+    Movement(Box<Movement>),
+    /// Steps given for a piece facing up, which are turned to face forward for each color
+    Leap(Vec<(i32, i32)>),
 }
 
 /// Enum describing different options for a distance a piece 
@@ -52,6 +61,7 @@ pub enum Distance {
 }
 
 impl Distance {
+    /// Checks if a move of `len` steps has the right distance
     pub fn correct(&self, len: &usize) -> bool {
         match self {
             Distance::N => true,
@@ -75,10 +85,7 @@ impl Movement {
 
         let parlett = match ParlettParser::parse(Rule::parlett, parlett_str) {
             Ok(mut x) => x.next().unwrap(),
-            Err(_) => {
-                error!("Failed to parse parlett: {}", parlett_str);
-                return Err(Error::ParlettParse)
-            },
+            Err(e) => return Err(Error::ParlettParse { input: parlett_str.to_string(), position: parse_position(&e) }),
         };
         let mut movement = Movement::new();
 
@@ -99,6 +106,8 @@ impl Movement {
                     let mut smove = Movement::new();
                     let p = pair.into_inner().next().unwrap();
                     parse(p, &mut smove);
+                    // Pieces on the way block each grouped move, unless it leaps
+                    movem.lame = !smove.leaper;
                     movem.direction.push(Direction::Movement(Box::new(smove)));
                 }
                 Rule::property => {
@@ -106,7 +115,6 @@ impl Movement {
                         "i" => movem.initial = true,
                         "c" => movem.capture = true,
                         "o" => movem.nocapture = true,
-                        "&" => movem.repeat = Some(Distance::N),
                         _ => unreachable!(),
                     }
                 },
@@ -114,9 +122,11 @@ impl Movement {
                     match pair.as_str() {
                         "~" => movem.leaper = true,
                         "^" => movem.locust = true,
+                        "&" => movem.repeat = Some(Distance::N),
                         _ => unreachable!(),
                     }
                 },
+                Rule::repeat => movem.repeat = Some(Distance::N),
                 Rule::range => {
                     let mut splt = pair.as_str().split('-');
                     movem.distance = Distance::Range {
@@ -140,6 +150,7 @@ impl Movement {
                 }
                 Rule::direction => movem.direction.push(Direction::from_str(pair.as_str())),
                 Rule::directions => pair.into_inner().for_each(|x| parse(x, movem)),
+                Rule::EOI => (),
             }
         } 
 
@@ -147,6 +158,21 @@ impl Movement {
         Ok(movement)
     }
 
+    /// Checks if the piece can stop after `len` steps. Repeated moves can stop after any number
+    /// of their steps
+    pub fn reaches(&self, len: usize) -> bool {
+        match &self.repeat {
+            Some(repeat) => repeat.correct(&len),
+            None => self.distance.correct(&len),
+        }
+    }
+
+    /// Checks if the piece moves on past other pieces. Repeated leaps leap over the squares
+    /// between their steps, but stop at the first piece they land on
+    pub fn passes_pieces(&self) -> bool {
+        self.leaper && self.repeat.is_none()
+    }
+
     /// Get a new `Movement` with no direction
     pub fn new() -> Movement {
         Movement {
@@ -159,13 +185,58 @@ impl Movement {
                 nocapture: false,
                 leaper: false,
                 locust: false,
+                lame: false,
+                hopper: false,
+                grasshopper: false,
         }
     }
 }
 
 
-fn if_white(color: PieceColor, a: Vec<(i32, i32)>, b: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
-    if color == PieceColor::White {a} else {b}
+/// Steps of each direction
+const ALL: [(i32, i32); 8] = [(1,0), (-1, 0), (0,1), (0,-1), (1,1), (-1,1), (-1,-1), (1,-1)];
+const DIAGONAL: [(i32, i32); 4] = [(1,1), (-1,1), (-1,-1), (1,-1)];
+const ORTHOGONAL: [(i32, i32); 4] = [(1,0), (-1, 0), (0,1), (0,-1)];
+const HORIZONTAL: [(i32, i32); 2] = [(1,0), (-1,0)];
+const VERTICAL: [(i32, i32); 2] = [(0,1), (0,-1)];
+const DIAGONAL_UP: [(i32, i32); 2] = [(1,-1), (-1,-1)];
+const DIAGONAL_DOWN: [(i32, i32); 2] = [(1,1), (-1,1)];
+const DIAGONAL_LEFT: [(i32, i32); 2] = [(-1,1), (-1,-1)];
+const DIAGONAL_RIGHT: [(i32, i32); 2] = [(1,1), (1,-1)];
+const UP: [(i32, i32); 1] = [(0,-1)];
+const DOWN: [(i32, i32); 1] = [(0,1)];
+const LEFT: [(i32, i32); 1] = [(-1,0)];
+const RIGHT: [(i32, i32); 1] = [(1,0)];
+
+/// Steps on hex boards. Orthogonal steps are listed clockwise from up, and each diagonal step
+/// goes between two neighbouring orthogonal steps
+const HEX_ALL: [(i32, i32); 12] = [(0,-1), (1,-1), (1,0), (0,1), (-1,1), (-1,0),
+                                    (1,-2), (2,-1), (1,1), (-1,2), (-2,1), (-1,-1)];
+const HEX_ORTHOGONAL: [(i32, i32); 6] = [(0,-1), (1,-1), (1,0), (0,1), (-1,1), (-1,0)];
+const HEX_DIAGONAL: [(i32, i32); 6] = [(1,-2), (2,-1), (1,1), (-1,2), (-2,1), (-1,-1)];
+/// Orthogonal steps at 60 degrees from facing up, down, left or right
+const HEX_OBLIQUE_UP: [(i32, i32); 2] = [(1,-1), (-1,0)];
+const HEX_OBLIQUE_DOWN: [(i32, i32); 2] = [(1,0), (-1,1)];
+const HEX_OBLIQUE_LEFT: [(i32, i32); 2] = [(-1,1), (0,-1)];
+const HEX_OBLIQUE_RIGHT: [(i32, i32); 2] = [(1,-1), (0,1)];
+/// Diagonal steps at right angles to facing up or down, and left or right
+const HEX_ACROSS_VERTICAL: [(i32, i32); 2] = [(2,-1), (-2,1)];
+const HEX_ACROSS_HORIZONTAL: [(i32, i32); 2] = [(1,-2), (-1,2)];
+
+/// Picks the steps facing the same way as `step`
+fn facing(step: (i32, i32), up: &'static [(i32, i32)], down: &'static [(i32, i32)],
+          left: &'static [(i32, i32)], right: &'static [(i32, i32)]) -> &'static [(i32, i32)] {
+    match step {
+        (0, dy) if dy < 0 => up,
+        (0, _) => down,
+        (dx, _) if dx < 0 => left,
+        _ => right,
+    }
+}
+
+/// Turns a step for a piece facing up to face `forward`
+fn turn(step: (i32, i32), forward: (i32, i32)) -> (i32, i32) {
+    (-forward.1 * step.0 - forward.0 * step.1, forward.0 * step.0 - forward.1 * step.1)
 }
 
 impl Direction {
@@ -184,29 +255,93 @@ impl Direction {
         }
     }
 
+    /// Get the steps of a direction that doesn't depend on the distance of the movement
+    pub fn steps(&self, color: &PieceColor, geometry: Geometry) -> Option<&'static [(i32, i32)]> {
+        if geometry == Geometry::Hex {
+            return self.hex_steps(color);
+        }
+        match self {
+            Direction::All => Some(&ALL),
+            Direction::Diagonal => Some(&DIAGONAL),
+            Direction::DiagonalForward =>
+                Some(facing(color.forward(), &DIAGONAL_UP, &DIAGONAL_DOWN, &DIAGONAL_LEFT, &DIAGONAL_RIGHT)),
+            Direction::DiagonalBackward =>
+                Some(facing(color.forward(), &DIAGONAL_DOWN, &DIAGONAL_UP, &DIAGONAL_RIGHT, &DIAGONAL_LEFT)),
+            Direction::Orthogonal => Some(&ORTHOGONAL),
+            Direction::OrthogonalSideways => Some(facing(color.forward(), &HORIZONTAL, &HORIZONTAL, &VERTICAL, &VERTICAL)),
+            Direction::OrthogonalForward => Some(facing(color.forward(), &UP, &DOWN, &LEFT, &RIGHT)),
+            Direction::OrthogonalBackward => Some(facing(color.forward(), &DOWN, &UP, &RIGHT, &LEFT)),
+            Direction::Hippogonal | Direction::Movement(_) | Direction::Leap(_) => None,
+        }
+    }
+
+    /// Get the steps of a direction on a hex board. Forward and backward diagonal steps are the
+    /// orthogonal steps at 60 degrees from forward and backward, which is how pawns capture in
+    /// Gliński's and McCooey's chess, and sideways steps are the diagonals at right angles to
+    /// forward
+    fn hex_steps(&self, color: &PieceColor) -> Option<&'static [(i32, i32)]> {
+        match self {
+            Direction::All => Some(&HEX_ALL),
+            Direction::Diagonal => Some(&HEX_DIAGONAL),
+            Direction::DiagonalForward => Some(facing(color.forward(),
+                &HEX_OBLIQUE_UP, &HEX_OBLIQUE_DOWN, &HEX_OBLIQUE_LEFT, &HEX_OBLIQUE_RIGHT)),
+            Direction::DiagonalBackward => Some(facing(color.forward(),
+                &HEX_OBLIQUE_DOWN, &HEX_OBLIQUE_UP, &HEX_OBLIQUE_RIGHT, &HEX_OBLIQUE_LEFT)),
+            Direction::Orthogonal => Some(&HEX_ORTHOGONAL),
+            Direction::OrthogonalSideways => Some(facing(color.forward(),
+                &HEX_ACROSS_VERTICAL, &HEX_ACROSS_VERTICAL, &HEX_ACROSS_HORIZONTAL, &HEX_ACROSS_HORIZONTAL)),
+            Direction::OrthogonalForward => Some(facing(color.forward(), &UP, &DOWN, &LEFT, &RIGHT)),
+            Direction::OrthogonalBackward => Some(facing(color.forward(), &DOWN, &UP, &RIGHT, &LEFT)),
+            Direction::Hippogonal | Direction::Movement(_) | Direction::Leap(_) => None,
+        }
+    }
+
     /// Get coordinate direction from direction
-    pub fn get_coords(&self, color: &PieceColor, distance: &Distance) -> Vec<(i32,i32)> {
+    pub fn get_coords(&self, color: &PieceColor, distance: &Distance, geometry: Geometry) -> Vec<(i32,i32)> {
         match self {
-            Direction::All => vec![(1,0), (-1, 0), (0,1), (0,-1), (1,1), (-1,1), (-1,-1), (1,-1)],
-            Direction::Diagonal => vec![(1,1), (-1,1), (-1,-1), (1,-1)],
-            Direction::DiagonalForward => if_white(*color, vec![(1,-1), (-1,-1)], vec![(1,1), (-1,1)]),
-            Direction::DiagonalBackward => if_white(*color, vec![(1,1), (-1,1)], vec![(1,-1), (-1,-1)]),
-            Direction::Orthogonal => vec![(1,0), (-1, 0), (0,1), (0,-1)],
-            Direction::OrthogonalSideways => vec![(1,0), (0,1)],
-            Direction::OrthogonalForward => if_white(*color, vec![(0,-1)], vec![(0,1)]),
-            Direction::OrthogonalBackward => if_white(*color, vec![(0,1)], vec![(0,-1)]),
+            Direction::All
+            | Direction::Orthogonal
+            | Direction::OrthogonalForward
+            | Direction::OrthogonalBackward
+            | Direction::OrthogonalSideways
+            | Direction::Diagonal
+            | Direction::DiagonalForward
+            | Direction::DiagonalBackward => self.steps(color, geometry).unwrap_or_default().to_vec(),
             Direction::Hippogonal => {
-                match distance {
-                    Distance::Hippogonal{m,n} =>
+                match (distance, geometry) {
+                    (Distance::Hippogonal{m,n}, Geometry::Square) =>
                         vec![(*m as i32, *n as i32),(*m as i32, -(*n as i32)),
                         (-(*m as i32), *n as i32),(-(*m as i32), -(*n as i32)),
                         (*n as i32, *m as i32),(*n as i32, -(*m as i32)),
                         (-(*n as i32), *m as i32),(-(*n as i32), -(*m as i32))
                         ],
-                        _ => unreachable!(),
+                    // `m` steps in one orthogonal direction and `n` in a direction next to it
+                    (Distance::Hippogonal{m,n}, Geometry::Hex) => {
+                        let mut coords = vec![];
+                        for (i, a) in HEX_ORTHOGONAL.iter().enumerate() {
+                            for b in &[HEX_ORTHOGONAL[(i + 1) % 6], HEX_ORTHOGONAL[(i + 5) % 6]] {
+                                coords.push((a.0 * *m as i32 + b.0 * *n as i32, a.1 * *m as i32 + b.1 * *n as i32));
+                            }
+                        }
+                        coords.sort();
+                        coords.dedup();
+                        coords
+                    },
+                    _ => unreachable!(),
                 }
             }
-            _ => unimplemented!()
+            Direction::Leap(steps) => steps.iter().map(|step| turn(*step, color.forward())).collect(),
+            // Each step of a group is a whole grouped move
+            Direction::Movement(group) => {
+                let scale = match group.distance {
+                    Distance::Set(len) => len as i32,
+                    _ => 1,
+                };
+                group.direction.iter()
+                    .flat_map(|direction| direction.get_coords(color, &group.distance, geometry))
+                    .map(|(x, y)| (x * scale, y * scale))
+                    .collect()
+            },
         }
     }
 }
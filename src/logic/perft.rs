@@ -0,0 +1,106 @@
+use crate::{
+    rules::{self, WinnerState},
+    logic::{
+        board::GameBoard,
+        color::PieceColor,
+        piece::PieceList,
+        move_gen::{GameMove, RhaiEnv, legal_moves},
+    },
+};
+use std::{
+    rc::Rc,
+    cell::RefCell,
+};
+use rhai::Scope;
+
+/// Counts the positions reachable from a board, used to test move generation
+pub struct Perft<'a> {
+    pub pieces: &'a PieceList,
+    /// Colors in turn order
+    pub colors: &'a [PieceColor],
+    /// Rhai environment used to generate extra moves and run callbacks after moves
+    pub rhai_env: Option<RhaiEnv<'a>>,
+    /// Board seen by rhai functions, which is set to the position moves are generated for or
+    /// callbacks are run on
+    pub shared_board: Option<Rc<RefCell<GameBoard>>>,
+    /// Winner set by rhai callbacks, which ends the game
+    pub winner: Option<WinnerState>,
+}
+
+impl Perft<'_> {
+
+    /// Counts the positions reachable in `depth` moves
+    pub fn perft(&self, board: &GameBoard, turn: PieceColor, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves(board, turn);
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves.iter()
+            .map(|mov| self.perft_after(board, mov, turn, depth))
+            .sum()
+    }
+
+    /// Counts the positions reachable in `depth` moves after each legal move
+    pub fn divide(&self, board: &GameBoard, turn: PieceColor, depth: usize) -> Vec<(GameMove, u64)> {
+        if depth == 0 {
+            return vec![];
+        }
+        self.legal_moves(board, turn)
+            .into_iter()
+            .map(|mov| {
+                let nodes = self.perft_after(board, &mov, turn, depth);
+                (mov, nodes)
+            })
+            .collect()
+    }
+
+    /// Counts the positions reachable in `depth` moves that start with `mov`. No moves follow a
+    /// move that ends the game
+    fn perft_after(&self, board: &GameBoard, mov: &GameMove, turn: PieceColor, depth: usize) -> u64 {
+        match self.after_move(board, mov) {
+            Some(nboard) => self.perft(&nboard, self.next(turn), depth - 1),
+            None if depth == 1 => 1,
+            None => 0,
+        }
+    }
+
+    /// Generates the moves that don't leave a royal piece of the player in check
+    fn legal_moves(&self, board: &GameBoard, turn: PieceColor) -> Vec<GameMove> {
+        if let Some(shared_board) = &self.shared_board {
+            *shared_board.borrow_mut() = board.clone();
+        }
+        legal_moves(turn, self.pieces, board, self.rhai_env)
+    }
+
+    /// Makes a move on a copy of the board and runs the rhai callbacks of the moved piece. Returns
+    /// `None` if a callback ended the game
+    fn after_move(&self, board: &GameBoard, mov: &GameMove) -> Option<GameBoard> {
+        let symbol = &board.board[mov.from.1][mov.from.0].as_ref().unwrap().symbol;
+        let piece = &self.pieces[symbol];
+        let mut nboard = board.clone();
+        let take = nboard.do_move(mov).unwrap();
+        let has_callbacks = piece.after_move.is_some() || (take && piece.after_take.is_some());
+        if let (true, Some(env), Some(shared_board)) = (has_callbacks, self.rhai_env, &self.shared_board) {
+            *shared_board.borrow_mut() = nboard;
+            if let Err(e) = rules::run_callbacks(env.engine, env.ast, &mut Scope::new(), symbol, piece, mov, take) {
+                env.report(e);
+            }
+            nboard = shared_board.borrow().clone();
+            if let Some(winner) = &self.winner {
+                if winner.borrow_mut().take().is_some() {
+                    return None;
+                }
+            }
+        }
+        Some(nboard)
+    }
+
+    /// Gets the player that plays after `color`
+    fn next(&self, color: PieceColor) -> PieceColor {
+        let index = self.colors.iter().position(|c| *c == color).unwrap_or(0);
+        self.colors[(index + 1) % self.colors.len()]
+    }
+}
@@ -1,10 +1,18 @@
 use crate::error::Error;
 use crate::logic::movement::Movement;
-use crate::logic::color::PieceColor;
+use crate::logic::betza;
+use crate::logic::color::{PieceColor, Teams};
+use crate::logic::board::GameBoard;
+use crate::logic::geometry::{Geometry, Wrap};
+use crate::logic::move_gen::gen_moves_for_piece;
+use serde::{Serialize, Deserialize};
 use std::{
     collections::HashMap,
 };
 
+/// Size of the empty board used to estimate the value of pieces
+const ESTIMATE_BOARD_SIZE: usize = 8;
+
 /// A struct for describing a general piece with any number
 /// of movement rules.
 #[derive(Debug, Clone)]
@@ -12,13 +20,66 @@ pub struct Piece {
     pub name: String,
     pub value: f32,
     pub moves: Vec<Movement>,
-    pub after_move: Option<String>,
-    pub after_take: Option<String>,
-    pub extra_moves: Option<String>,
+    pub after_move: Option<Callback>,
+    pub after_take: Option<Callback>,
+    pub extra_moves: Option<Callback>,
     pub kingstatus: bool,
+    pub promotion: Option<Promotion>,
+    pub castling: Vec<Castling>,
+    pub en_passant: bool,
+    /// Whether moving the piece resets the move limit
+    pub resets_move_limit: bool,
+}
+
+/// A rhai function run for a piece, and the line of the rules file it was given on
+#[derive(Debug, Clone)]
+pub struct Callback {
+    pub name: String,
+    pub line: usize,
+}
+
+/// Describes where a piece promotes and which pieces it can promote to
+#[derive(Debug, Clone)]
+pub struct Promotion {
+    /// Ranks the piece promotes on, counted from the players own side starting at 1
+    pub ranks: Vec<usize>,
+    /// Symbols of the pieces that the piece can promote to
+    pub pieces: Vec<String>,
 }
 
+/// Describes castling with a partner piece on the same row
 #[derive(Debug, Clone)]
+pub struct Castling {
+    /// Symbol of the partner piece
+    pub partner: String,
+    /// Number of squares the piece moves towards the partner
+    pub distance: usize,
+}
+
+impl Promotion {
+    /// Checks if a piece of `color` promotes when moving to `pos`. Ranks are counted back from
+    /// the edge of the board in front of the piece, skipping voids, so the last square of each
+    /// file is on the last rank of a hex board
+    pub fn on_rank(&self, color: PieceColor, pos: (usize, usize), board: &GameBoard) -> bool {
+        let (dx, dy) = color.forward();
+        let ranks = if dx == 0 { board.height } else { board.width };
+        let mut ahead = 0;
+        let (mut x, mut y) = pos;
+        loop {
+            x = (x as i32 + dx) as usize;
+            y = (y as i32 + dy) as usize;
+            if x >= board.width || y >= board.height {
+                break;
+            }
+            if !board.is_void(x, y) {
+                ahead += 1;
+            }
+        }
+        self.ranks.contains(&(ranks - ahead))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GamePiece {
     pub symbol: String,
     pub color: PieceColor,
@@ -26,13 +87,37 @@ pub struct GamePiece {
 }
 
 impl Piece {
+    /// Create a piece from Betza notation, like `WfcF`
+    pub fn from_betza(name: &str, value: f32, betza: &str) -> Result<Piece, Error> {
+        Ok(Piece {
+            name: name.to_string(),
+            value,
+            moves: betza::parse(betza)?,
+            after_move: None,
+            after_take: None,
+            extra_moves: None,
+            kingstatus: false,
+            promotion: None,
+            castling: vec![],
+            en_passant: false,
+            resets_move_limit: false,
+        })
+    }
+
     /// Create a piece, from a parlett string with any number
     /// of movement rules.
     pub fn from_parlett(p_name: &str, value: f32, parlett: &str) -> Result<Piece, Error> {
-        let split = parlett.split(',');
         let name = p_name.to_string();
-        // TODO: Remove unwrap
-        let moves = split.map(|x| Movement::from_parlett(x).unwrap()).collect::<Vec<Movement>>();
+        let mut moves = vec![];
+        // Errors point at the character in the whole string, not just the failing movement
+        let mut offset = 0;
+        for x in parlett.split(',') {
+            moves.push(Movement::from_parlett(x).map_err(|e| match e {
+                Error::ParlettParse { position, .. } => Error::ParlettParse { input: parlett.to_string(), position: position + offset },
+                e => e,
+            })?);
+            offset += x.len() + 1;
+        }
 
         Ok(Piece {
             name,
@@ -42,8 +127,47 @@ impl Piece {
             after_take: None,
             extra_moves: None,
             kingstatus: false,
+            promotion: None,
+            castling: vec![],
+            en_passant: false,
+            resets_move_limit: false,
         })
     }
+
+    /// Estimates the value of a piece from its mobility, which is the average number of squares
+    /// it can move to from each square of an empty 8x8 board. A queen is worth about 9
+    pub fn estimate_value(&self) -> f32 {
+        let symbol = String::from("?");
+        let mut pieces = PieceList::new();
+        pieces.insert(symbol.clone(), self.clone());
+        let mut board = GameBoard {
+            width: ESTIMATE_BOARD_SIZE,
+            height: ESTIMATE_BOARD_SIZE,
+            board: vec![vec![None; ESTIMATE_BOARD_SIZE]; ESTIMATE_BOARD_SIZE],
+            last_move: None,
+            teams: Teams::default(),
+            geometry: Geometry::Square,
+            wrap: Wrap::default(),
+            voids: vec![],
+        };
+        let mut targets = 0;
+        for y in 0..ESTIMATE_BOARD_SIZE {
+            for x in 0..ESTIMATE_BOARD_SIZE {
+                let piece = GamePiece { symbol: symbol.clone(), color: PieceColor::White, has_moved: false };
+                board.board[y][x] = Some(piece.clone());
+                let mut moves = vec![];
+                gen_moves_for_piece(&PieceColor::White, &x, &y, &piece, &pieces, &board, &mut moves);
+                // The same square can be reached by several movement rules
+                let mut squares: Vec<(usize, usize)> = moves.into_iter().map(|m| m.to).collect();
+                squares.sort_unstable();
+                squares.dedup();
+                targets += squares.len();
+                board.board[y][x] = None;
+            }
+        }
+        let mobility = targets as f32 / (ESTIMATE_BOARD_SIZE * ESTIMATE_BOARD_SIZE) as f32;
+        (mobility * 0.4).max(1.0)
+    }
 }
 
 pub type PieceList = HashMap<String, Piece>;
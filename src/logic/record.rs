@@ -0,0 +1,134 @@
+use crate::logic::{
+    board::GameBoard,
+    color::{PieceColor, Teams},
+    move_gen::GameMove,
+    piece::PieceList,
+    result::GameResult,
+};
+use std::fmt;
+
+/// Maximum length of a line of move text
+const LINE_LENGTH: usize = 80;
+
+/// Record of a game in a PGN-like format
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameRecord {
+    /// Tag pairs describing the game
+    pub tags: Vec<(String, String)>,
+    /// Moves in the order they were made
+    pub moves: Vec<String>,
+    /// Number of players taking turns
+    pub players: usize,
+    /// Result written after the moves
+    pub result: String,
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in &self.tags {
+            writeln!(f, "[{} \"{}\"]", name, value.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+        writeln!(f)?;
+        // Move text is numbered after each round and wrapped into lines
+        let mut tokens = vec![];
+        for (i, mov) in self.moves.iter().enumerate() {
+            if i % self.players.max(1) == 0 {
+                tokens.push(format!("{}.", i / self.players.max(1) + 1));
+            }
+            tokens.push(mov.clone());
+        }
+        tokens.push(self.result.clone());
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + token.len() + 1 > LINE_LENGTH {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        writeln!(f, "{}", line)
+    }
+}
+
+/// Gets the name of a file. Files after `z` are named `aa`, `ab` and so on, so boards of any
+/// width can be written
+pub fn file_name(x: usize) -> String {
+    let mut name = vec![];
+    let mut n = x + 1;
+    while n > 0 {
+        n -= 1;
+        name.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    name.iter().rev().collect()
+}
+
+/// Gets the name of a square, where rank 1 is the bottom row of the board
+pub fn square_name(pos: (usize, usize), height: usize) -> String {
+    format!("{}{}", file_name(pos.0), height - pos.1)
+}
+
+/// Gets the symbol of a piece as written in the record. Symbols with more than one letter are
+/// put in parentheses
+pub fn piece_name(symbol: &str) -> String {
+    if symbol.chars().count() == 1 {
+        symbol.to_ascii_uppercase()
+    } else {
+        format!("({})", symbol)
+    }
+}
+
+/// Writes a move in long algebraic notation. Has to be called before the move is made on the
+/// board. Pieces that can promote are written without a symbol, like pawns
+pub fn move_notation(gamemove: &GameMove, board: &GameBoard, pieces: &PieceList) -> String {
+    let (fx, fy) = gamemove.from;
+    let (tx, ty) = gamemove.to;
+    if gamemove.castle.is_some() {
+        return String::from(if tx > fx { "O-O" } else { "O-O-O" });
+    }
+    let symbol = &board.board[fy][fx].as_ref().unwrap().symbol;
+    let mut notation = match pieces.get(symbol) {
+        Some(piece) if piece.promotion.is_some() => String::new(),
+        _ => piece_name(symbol),
+    };
+    let take = board.board[ty][tx].is_some() || gamemove.captures.is_some();
+    notation.push_str(&square_name(gamemove.from, board.height));
+    notation.push(if take { 'x' } else { '-' });
+    notation.push_str(&square_name(gamemove.to, board.height));
+    if let Some(promotion) = &gamemove.promotion {
+        notation.push('=');
+        notation.push_str(&piece_name(promotion));
+    }
+    notation
+}
+
+/// Writes the result of a game as the score of each player in turn order, so a win for white in
+/// a two player game is `1-0`. The allies of the winner share the win
+pub fn result_notation(result: &GameResult, colors: &[PieceColor], teams: &Teams) -> String {
+    let scores: Vec<&str> = match result {
+        GameResult::Ongoing | GameResult::Eliminated(..) => return String::from("*"),
+        GameResult::Win(winner, _) => colors.iter()
+            .map(|color| if teams.allied(*color, *winner) { "1" } else { "0" })
+            .collect(),
+        GameResult::Draw(_) => colors.iter().map(|_| "1/2").collect(),
+    };
+    scores.join("-")
+}
+
+/// Formats a date given as seconds since the unix epoch as `YYYY.MM.DD`
+pub fn date_notation(secs: u64) -> String {
+    // Converts days since the epoch to a civil date
+    let days = (secs / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
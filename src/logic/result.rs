@@ -0,0 +1,100 @@
+use crate::logic::{
+    board::GameBoard,
+    color::PieceColor,
+    piece::PieceList,
+    move_gen,
+};
+use serde::{Serialize, Deserialize};
+
+/// Outcome of a game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GameResult {
+    /// The game has not ended yet
+    Ongoing,
+    /// A player has won the game
+    Win(PieceColor, ResultReason),
+    /// The game ended in a draw
+    Draw(ResultReason),
+    /// A player lost, but the game goes on without them as more than one other player is left
+    Eliminated(PieceColor, ResultReason),
+}
+
+/// Reason for a game ending
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultReason {
+    /// The king of the losing player can't escape check
+    Checkmate,
+    /// The player to move has no legal moves but isn't in check
+    Stalemate,
+    /// The same position has occured too many times
+    Repetition,
+    /// Too many moves without a capture or a pawn move
+    MoveLimit,
+    /// Only kings are left on the board
+    InsufficientMaterial,
+    /// The result was set by the rules script
+    Rules,
+    /// The losing player resigned
+    Resignation,
+    /// The players agreed to a draw
+    Agreement,
+    /// The losing player ran out of time
+    Timeout,
+    /// The losing player disconnected and didn't rejoin in time
+    Abandonment,
+}
+
+/// What happens to a player that is stalemated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StalemateRule {
+    Draw,
+    Loss,
+    Win,
+}
+
+impl StalemateRule {
+    /// Get the corrosponding `StalemateRule` to a string from a rules file
+    pub fn from_str(rule: &str) -> Option<StalemateRule> {
+        match rule {
+            "draw" => Some(StalemateRule::Draw),
+            "loss" => Some(StalemateRule::Loss),
+            "win" => Some(StalemateRule::Win),
+            _ => None,
+        }
+    }
+}
+
+/// Creates a key identifying the position on the board and the player to move, used to detect
+/// repeated positions. Positions only repeat if the same castling and en passant captures are
+/// possible, so the key includes whether pieces that castle have moved and the last move if it can
+/// be captured en passant
+pub fn position_key(board: &GameBoard, pieces: &PieceList, turn: PieceColor) -> String {
+    let castles = |symbol: &str| pieces.iter()
+        .any(|(id, piece)| !piece.castling.is_empty() && (id == symbol || piece.castling.iter().any(|c| c.partner == symbol)));
+    let mut key = format!("{}:", turn as u8);
+    for row in &board.board {
+        for square in row {
+            match square {
+                Some(piece) => {
+                    key.push_str(&format!("{}{}", piece.symbol, piece.color as u8));
+                    if piece.has_moved && castles(&piece.symbol) {
+                        key.push('*');
+                    }
+                    key.push(',');
+                },
+                None => key.push(','),
+            }
+        }
+        key.push('/');
+    }
+    if let Some((last_move, _)) = move_gen::en_passant_move(board, pieces) {
+        key.push_str(&format!("{:?}{:?}", last_move.from, last_move.to));
+    }
+    key
+}
+
+/// Checks if only royal pieces are left on the board, so neither player can win
+pub fn insufficient_material(board: &GameBoard, pieces: &PieceList) -> bool {
+    board.get_positions_of(&|piece| !pieces[&piece.symbol].kingstatus).is_empty()
+}
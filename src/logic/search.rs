@@ -0,0 +1,196 @@
+use crate::logic::{
+    board::GameBoard,
+    color::PieceColor,
+    piece::PieceList,
+    move_gen::{GameMove, in_check, legal_moves},
+    result::StalemateRule,
+};
+use serde::{Serialize, Deserialize};
+use std::time::{Duration, Instant};
+
+/// Score of a checkmate, larger than any material balance
+const MATE_SCORE: f32 = 1_000_000.0;
+
+/// How long and how deep a bot searches for a move
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SearchLimits {
+    /// Number of moves to look ahead
+    pub depth: usize,
+    /// Time after which the search is stopped and the best move found so far is played
+    pub time: Option<Duration>,
+}
+
+impl SearchLimits {
+
+    /// Limits the search to at most the depth and time of `max`. Searches without a time limit get
+    /// the one of `max`
+    pub fn capped(self, max: SearchLimits) -> SearchLimits {
+        SearchLimits {
+            depth: self.depth.min(max.depth),
+            time: match (self.time, max.time) {
+                (Some(time), Some(max_time)) => Some(time.min(max_time)),
+                (time, max_time) => max_time.or(time),
+            },
+        }
+    }
+}
+
+/// A move of the searching player with the position it leads to
+#[derive(Debug, Clone)]
+pub struct RootMove {
+    pub mov: GameMove,
+    /// Board after the move and the rhai callbacks it triggered
+    pub board: GameBoard,
+    /// Winner declared by the rhai callbacks
+    pub winner: Option<PieceColor>,
+}
+
+/// Alpha-beta search over the moves of every player. Players other than the searching player are
+/// assumed to play against it
+struct Search<'a> {
+    pieces: &'a PieceList,
+    colors: &'a [PieceColor],
+    /// Player searching for a move
+    root: PieceColor,
+    /// Outcome for a player without legal moves that isn't in check
+    stalemate: StalemateRule,
+    deadline: Option<Instant>,
+    /// Set when the deadline is reached, so the results of the current depth are discarded
+    stopped: bool,
+}
+
+/// Finds the best of the moves in `roots` for `turn`. The positions after the root moves are made
+/// by the game, so they include the effects of rhai callbacks. Deeper moves are generated and made
+/// without rhai.
+pub fn best_move(roots: Vec<RootMove>, pieces: &PieceList, colors: &[PieceColor], turn: PieceColor,
+                 limits: SearchLimits, stalemate: StalemateRule) -> Option<GameMove> {
+    let mut search = Search {
+        pieces,
+        colors,
+        root: turn,
+        stalemate,
+        deadline: limits.time.map(|time| Instant::now() + time),
+        stopped: false,
+    };
+    let mut ordered = roots;
+    // Moves leading to the best material balance are searched first, so more is pruned
+    ordered.sort_by(|a, b| search.score_root(b, 0).partial_cmp(&search.score_root(a, 0)).unwrap_or(std::cmp::Ordering::Equal));
+    let mut best = ordered.first().map(|root| root.mov.clone());
+    // Iterative deepening, so a move is ready when time runs out
+    for depth in 1..=limits.depth.max(1) {
+        let mut alpha = f32::NEG_INFINITY;
+        let mut depth_best = None;
+        for (index, root) in ordered.iter().enumerate() {
+            let score = match root.winner {
+                Some(_) => search.score_root(root, depth),
+                None => search.search(&root.board, search.next(turn), depth - 1, alpha, f32::INFINITY),
+            };
+            if search.stopped {
+                break;
+            }
+            if score > alpha || depth_best.is_none() {
+                alpha = score;
+                depth_best = Some(index);
+            }
+        }
+        if search.stopped {
+            break;
+        }
+        if let Some(index) = depth_best {
+            // Searching the best move first makes the next depth prune more
+            let root = ordered.remove(index);
+            best = Some(root.mov.clone());
+            ordered.insert(0, root);
+        }
+    }
+    best
+}
+
+impl Search<'_> {
+
+    /// Scores a position from the perspective of the searching player
+    fn search(&mut self, board: &GameBoard, turn: PieceColor, depth: usize, mut alpha: f32, mut beta: f32) -> f32 {
+        if matches!(self.deadline, Some(deadline) if Instant::now() >= deadline) {
+            self.stopped = true;
+        }
+        if depth == 0 || self.stopped {
+            return self.evaluate(board);
+        }
+        let moves = self.legal_moves(board, turn);
+        if moves.is_empty() {
+            // Faster mates are scored higher
+            let score = MATE_SCORE + depth as f32;
+            let lost = if board.teams.allied(turn, self.root) { -score } else { score };
+            return match self.stalemate {
+                _ if in_check(turn, self.pieces, board) => lost,
+                StalemateRule::Draw => 0.0,
+                StalemateRule::Loss => lost,
+                StalemateRule::Win => -lost,
+            };
+        }
+        let maximizing = board.teams.allied(turn, self.root);
+        let mut best = if maximizing { f32::NEG_INFINITY } else { f32::INFINITY };
+        for mov in self.order_moves(moves, board) {
+            let mut nboard = board.clone();
+            if nboard.do_move(&mov).is_err() {
+                continue;
+            }
+            let score = self.search(&nboard, self.next(turn), depth - 1, alpha, beta);
+            if maximizing {
+                best = best.max(score);
+                alpha = alpha.max(score);
+            } else {
+                best = best.min(score);
+                beta = beta.min(score);
+            }
+            if alpha >= beta || self.stopped {
+                break;
+            }
+        }
+        best
+    }
+
+    /// Scores a root move without searching further, using the winner declared by rhai if there is
+    /// one
+    fn score_root(&self, root: &RootMove, depth: usize) -> f32 {
+        let score = MATE_SCORE + depth as f32;
+        match root.winner {
+            Some(winner) if root.board.teams.allied(winner, self.root) => score,
+            Some(_) => -score,
+            None => self.evaluate(&root.board),
+        }
+    }
+
+    /// Material balance of the team of the searching player against the other players
+    fn evaluate(&self, board: &GameBoard) -> f32 {
+        board.board.iter()
+            .flatten()
+            .flatten()
+            .map(|piece| {
+                let value = self.pieces[&piece.symbol].value;
+                if board.teams.allied(piece.color, self.root) { value } else { -value }
+            })
+            .sum()
+    }
+
+    /// Generates the moves that don't leave a royal piece of the player in check
+    fn legal_moves(&self, board: &GameBoard, turn: PieceColor) -> Vec<GameMove> {
+        legal_moves(turn, self.pieces, board, None)
+    }
+
+    /// Puts captures of valuable pieces first, so more of the search is pruned
+    fn order_moves(&self, mut moves: Vec<GameMove>, board: &GameBoard) -> Vec<GameMove> {
+        let captured_value = |mov: &GameMove| {
+            let (x, y) = mov.captures.unwrap_or(mov.to);
+            board.board[y][x].as_ref().map_or(0.0, |piece| self.pieces[&piece.symbol].value)
+        };
+        moves.sort_by(|a, b| captured_value(b).partial_cmp(&captured_value(a)).unwrap_or(std::cmp::Ordering::Equal));
+        moves
+    }
+
+    /// Gets the player that plays after `color`
+    fn next(&self, color: PieceColor) -> PieceColor {
+        let index = self.colors.iter().position(|c| *c == color).unwrap_or(0);
+        self.colors[(index + 1) % self.colors.len()]
+    }
+}
@@ -1,7 +1,6 @@
 use crate::logic::*;
 use crate::logic::move_gen::*;
-use crate::logic::color::PieceColor;
-use crate::Error;
+use crate::logic::color::{PieceColor, Teams};
 
 /// Test to ensure pawns cant leap when they move 2 forward
 #[test]
@@ -41,28 +40,17 @@ fn perft_depth_3_moves() {
     assert_eq!(perft(3, PieceColor::White, &pieces, &board), 8902);
 }
 
-fn perft(depth: u32, color: PieceColor, pieces: &piece::PieceList, board: &board::GameBoard) -> u32 {
-    if depth == 0 {
-        return 1;
-    }
-    let opp_color = match color {
-        PieceColor::White => PieceColor::Black,
-        PieceColor::Black => PieceColor::White,
-        _ => unreachable!(),
+fn perft(depth: usize, color: PieceColor, pieces: &piece::PieceList, board: &board::GameBoard) -> u64 {
+    let mut pieces = pieces.clone();
+    pieces.get_mut("k").unwrap().kingstatus = true;
+    let perft = perft::Perft {
+        pieces: &pieces,
+        colors: &[PieceColor::White, PieceColor::Black],
+        rhai_env: None,
+        shared_board: None,
+        winner: None,
     };
-    let moves = generate_moves(color, &pieces, &board, None);
-    let mut n_pos = 0;
-
-    for mov in moves {
-        let mut nboard = board.clone();
-        assert!(!nboard.do_move(&mov).is_err());
-        let king = get_position_of_piece(&nboard, color, "k".to_string()).unwrap();
-        if !is_checked(color, &king.0, &king.1, pieces, &nboard) {
-            n_pos += perft(depth - 1, opp_color, pieces, &nboard);
-        }
-    }
-
-    return n_pos;
+    perft.perft(board, color, depth)
 }
 
 #[test]
@@ -88,8 +76,164 @@ fn orthogonal_movegen() {
 #[test]
 fn invalid_move() {
     let mut board = board::GameBoard::from_ffen("nnnn/4/4/NNNN").unwrap();
-    assert_eq!(board.do_move(&move_gen::GameMove{ from: (0,2), to: (1,1)}).is_ok(), false);
+    assert_eq!(board.do_move(&move_gen::GameMove::new(0, 2, 1, 1)).is_ok(), false);
 } 
+/// Test that a pawn reaching the last rank gets a move for each promotion piece
+#[test]
+fn promotion_moves() {
+    let mut pieces = helper_get_standard_pieces();
+    pieces.get_mut("p").unwrap().promotion = Some(piece::Promotion {
+        ranks: vec![4],
+        pieces: vec!["q".to_string(), "n".to_string()],
+    });
+    let mut board = board::GameBoard::from_ffen("1n2/P3/4/4").unwrap();
+
+    let moves = generate_moves(PieceColor::White, &pieces, &board, None);
+    assert_eq!(moves.len(), 4);
+    assert!(moves.iter().all(|m| m.promotion.is_some()));
+
+    let capture = moves.iter().find(|m| m.to == (1, 0) && m.promotion == Some("n".to_string())).unwrap();
+    assert!(board.do_move(capture).unwrap());
+    assert_eq!(board.board[0][1].as_ref().unwrap().symbol, "n");
+    assert_eq!(board.board[0][1].as_ref().unwrap().color, PieceColor::White);
+}
+
+/// Test castling to both sides and moving the partner piece
+#[test]
+fn castling_moves() {
+    let pieces = helper_get_castling_pieces();
+    let mut board = board::GameBoard::from_ffen("4k3/8/8/8/8/8/8/R3K2R").unwrap();
+
+    let castles: Vec<GameMove> = generate_moves(PieceColor::White, &pieces, &board, None)
+        .into_iter().filter(|m| m.castle.is_some()).collect();
+    assert_eq!(castles.len(), 2);
+
+    let short = castles.iter().find(|m| m.to == (6, 7)).unwrap();
+    assert_eq!(short.castle, Some(((7, 7), (5, 7))));
+    board.do_move(short).unwrap();
+    assert_eq!(board.board[7][6].as_ref().unwrap().symbol, "k");
+    assert_eq!(board.board[7][5].as_ref().unwrap().symbol, "r");
+    assert!(board.board[7][7].is_none());
+}
+
+/// Test that castling is not possible through check, out of check or after moving
+#[test]
+fn castling_restrictions() {
+    let pieces = helper_get_castling_pieces();
+    let castles = |board: &board::GameBoard| generate_moves(PieceColor::White, &pieces, board, None)
+        .into_iter().filter(|m| m.castle.is_some()).count();
+
+    // Rook on f8 attacks the square the king passes
+    assert_eq!(castles(&board::GameBoard::from_ffen("4kr2/8/8/8/8/8/8/4K2R").unwrap()), 0);
+    // Rook on e8 checks the king
+    assert_eq!(castles(&board::GameBoard::from_ffen("3kr3/8/8/8/8/8/8/R3K2R").unwrap()), 0);
+    // Pawn on e2 attacks d1 and f1
+    assert_eq!(castles(&board::GameBoard::from_ffen("4k3/8/8/8/8/8/4p3/R3K2R").unwrap()), 0);
+    // Pieces between king and rook
+    assert_eq!(castles(&board::GameBoard::from_ffen("4k3/8/8/8/8/8/8/RN2K1NR").unwrap()), 0);
+
+    let mut board = board::GameBoard::from_ffen("4k3/8/8/8/8/8/8/R3K2R").unwrap();
+    board.do_move(&GameMove::new(7, 7, 7, 6)).unwrap();
+    board.do_move(&GameMove::new(7, 6, 7, 7)).unwrap();
+    assert_eq!(castles(&board), 1);
+}
+
+/// Test en passant is only possible right after the opponent moves two squares
+#[test]
+fn en_passant_moves() {
+    let mut pieces = helper_get_standard_pieces();
+    pieces.get_mut("p").unwrap().en_passant = true;
+    let en_passant = |board: &board::GameBoard| generate_moves(PieceColor::White, &pieces, board, None)
+        .into_iter().filter(|m| m.captures.is_some()).collect::<Vec<GameMove>>();
+    let mut board = board::GameBoard::from_ffen("4k3/3p4/8/4P3/8/8/8/4K3").unwrap();
+
+    assert_eq!(en_passant(&board).len(), 0);
+    board.do_move(&GameMove::new(3, 1, 3, 3)).unwrap();
+    let moves = en_passant(&board);
+    assert_eq!(moves.len(), 1);
+    assert_eq!(moves[0].to, (3, 2));
+    assert_eq!(moves[0].captures, Some((3, 3)));
+
+    let mut captured = board.clone();
+    assert!(captured.do_move(&moves[0]).unwrap());
+    assert!(captured.board[3][3].is_none());
+
+    // Capture is no longer possible after another move
+    board.do_move(&GameMove::new(4, 7, 4, 6)).unwrap();
+    board.do_move(&GameMove::new(4, 0, 4, 1)).unwrap();
+    assert_eq!(en_passant(&board).len(), 0);
+}
+
+/// Test that perft runs the callbacks of moves and stops when a callback ends the game
+#[test]
+fn perft_callbacks() {
+    use structopt::StructOpt;
+
+    let limits = crate::args::ScriptLimits::from_iter(&["test"]);
+    let env = crate::rules::RulesEnv::new("games/atomic.rhai", &limits).unwrap();
+    let rules = env.rules.borrow();
+    let board = board::GameBoard::from_ffen("4k3/4p3/8/8/8/8/4R3/4K3").unwrap();
+    let perft = perft::Perft {
+        pieces: &rules.pieces,
+        colors: &rules.colors,
+        rhai_env: Some(RhaiEnv { ast: &env.ast, engine: &env.engine, errors: None }),
+        shared_board: Some(rules.board.clone()),
+        winner: Some(env.winner.clone()),
+    };
+    // Taking the pawn explodes the black king, so black has no replies
+    let divide = perft.divide(&board, PieceColor::White, 2);
+    let take = divide.iter().find(|(mov, _)| mov.to == (4, 1)).unwrap();
+    assert_eq!(take.1, 0);
+    assert!(divide.iter().all(|(mov, nodes)| mov.to == (4, 1) || *nodes > 0));
+}
+
+/// Test that positions are identified by pieces, the player to move and castling and en passant
+/// rights
+#[test]
+fn position_keys() {
+    let pieces = helper_get_castling_pieces();
+    let key = |board: &board::GameBoard, turn| result::position_key(board, &pieces, turn);
+    let mut board = board::GameBoard::from_ffen("4k3/8/8/8/8/8/8/4K1N1").unwrap();
+    let start = key(&board, PieceColor::White);
+    assert_ne!(start, key(&board, PieceColor::Black));
+
+    board.do_move(&GameMove::new(6, 7, 5, 5)).unwrap();
+    assert_ne!(start, key(&board, PieceColor::White));
+    board.do_move(&GameMove::new(5, 5, 6, 7)).unwrap();
+    assert_eq!(start, key(&board, PieceColor::White));
+
+    // The rook can't castle after moving away and back
+    let mut board = board::GameBoard::from_ffen("4k3/8/8/8/8/8/8/4K2R").unwrap();
+    let start = key(&board, PieceColor::White);
+    board.do_move(&GameMove::new(7, 7, 7, 6)).unwrap();
+    board.do_move(&GameMove::new(7, 6, 7, 7)).unwrap();
+    assert_ne!(start, key(&board, PieceColor::White));
+
+    // The pawn can only be captured en passant right after its double step
+    let mut pieces = helper_get_standard_pieces();
+    pieces.get_mut("p").unwrap().en_passant = true;
+    let mut board = board::GameBoard::from_ffen("4k3/8/8/8/8/8/4P3/4K3").unwrap();
+    board.do_move(&GameMove::new(4, 6, 4, 4)).unwrap();
+    let after_double_step = result::position_key(&board, &pieces, PieceColor::Black);
+    board.do_move(&GameMove::new(4, 0, 3, 0)).unwrap();
+    board.do_move(&GameMove::new(4, 7, 3, 7)).unwrap();
+    board.do_move(&GameMove::new(3, 0, 4, 0)).unwrap();
+    board.do_move(&GameMove::new(3, 7, 4, 7)).unwrap();
+    assert_ne!(after_double_step, result::position_key(&board, &pieces, PieceColor::Black));
+}
+
+/// Test that only kings left on the board is insufficient material
+#[test]
+fn insufficient_material() {
+    let mut pieces = helper_get_standard_pieces();
+    pieces.get_mut("k").unwrap().kingstatus = true;
+
+    let board = board::GameBoard::from_ffen("4k3/8/8/8/8/8/8/4K3").unwrap();
+    assert!(result::insufficient_material(&board, &pieces));
+    let board = board::GameBoard::from_ffen("4k3/8/8/8/8/8/8/4K1N1").unwrap();
+    assert!(!result::insufficient_material(&board, &pieces));
+}
+
 #[test]
 fn get_checkers_fn() {
     let pieces = helper_get_standard_pieces();
@@ -135,18 +279,575 @@ fn distance_correct_range() {
     }
 }
 
-/// Get the first found position of a piece
-fn get_position_of_piece(board: &board::GameBoard, color: PieceColor, symbol: String) -> Result<(usize, usize), Error> {
-    for y in 0..board.board.len() {
-        for x in 0..board.board[y].len() {
-            if let Some(piece) = &board.board[y][x] {
-                if piece.symbol == symbol && piece.color == color {
-                    return Ok((x,y));
-                }
-            }
-        }
+/// Test parsing of time controls
+#[test]
+fn time_control_parsing() {
+    use clock::TimeControl;
+    use std::time::Duration;
+
+    assert_eq!("300+2".parse::<TimeControl>().unwrap(),
+        TimeControl::Increment { base: Duration::from_secs(300), increment: Duration::from_secs(2) });
+    assert_eq!("60d5".parse::<TimeControl>().unwrap(),
+        TimeControl::Bronstein { base: Duration::from_secs(60), delay: Duration::from_secs(5) });
+    assert_eq!("30/move".parse::<TimeControl>().unwrap(), TimeControl::PerMove(Duration::from_secs(30)));
+    assert_eq!("600".parse::<TimeControl>().unwrap(),
+        TimeControl::Increment { base: Duration::from_secs(600), increment: Duration::default() });
+    assert!("abc".parse::<TimeControl>().is_err());
+    assert!("1e300+0".parse::<TimeControl>().is_err());
+    assert!("10+".parse::<TimeControl>().is_err());
+    assert!("-5".parse::<TimeControl>().is_err());
+}
+
+/// Test that clocks add increments and delays and detect players running out of time
+#[test]
+fn clock_times() {
+    use clock::{Clock, TimeControl};
+    use std::time::{Duration, Instant};
+
+    let colors = [PieceColor::White, PieceColor::Black];
+    let start = Instant::now();
+    let secs = Duration::from_secs;
+
+    let mut clock = Clock::new("10+2".parse::<TimeControl>().unwrap(), &colors);
+    clock.start_turn(PieceColor::White, start);
+    assert_eq!(clock.deadline(), Some(start + secs(10)));
+    assert!(clock.end_turn(start + secs(3)));
+    assert_eq!(clock.remaining(PieceColor::White, start + secs(3)), secs(9));
+    clock.start_turn(PieceColor::Black, start + secs(3));
+    assert_eq!(clock.remaining(PieceColor::Black, start + secs(5)), secs(8));
+    assert!(!clock.end_turn(start + secs(13)));
+    assert_eq!(clock.remaining(PieceColor::Black, start + secs(13)), Duration::default());
+
+    let mut clock = Clock::new("10d2".parse::<TimeControl>().unwrap(), &colors);
+    clock.start_turn(PieceColor::White, start);
+    assert!(clock.end_turn(start + secs(1)));
+    assert_eq!(clock.remaining(PieceColor::White, start), secs(10));
+    clock.start_turn(PieceColor::White, start);
+    assert!(clock.end_turn(start + secs(5)));
+    assert_eq!(clock.remaining(PieceColor::White, start), secs(7));
+
+    let mut clock = Clock::new("5/move".parse::<TimeControl>().unwrap(), &colors);
+    clock.start_turn(PieceColor::White, start);
+    assert!(clock.end_turn(start + secs(4)));
+    clock.start_turn(PieceColor::White, start);
+    assert_eq!(clock.remaining(PieceColor::White, start), secs(5));
+    assert!(!clock.end_turn(start + secs(5)));
+}
+
+/// Test that saved boards keep their pieces, moved flags and last move
+#[test]
+fn board_save_roundtrip() {
+    let mut board = board::GameBoard::from_ffen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap();
+    board.do_move(&GameMove::new(4, 6, 4, 4)).unwrap();
+
+    let saved = serde_json::to_string(&board).unwrap();
+    let restored: board::GameBoard = serde_json::from_str(&saved).unwrap();
+    let pawn = restored.board[4][4].as_ref().unwrap();
+    assert_eq!(pawn.symbol, "p");
+    assert_eq!(pawn.color, PieceColor::White);
+    assert!(pawn.has_moved);
+    assert!(restored.board[6][4].is_none());
+    assert_eq!(restored.last_move, Some(GameMove::new(4, 6, 4, 4)));
+    assert_eq!((restored.width, restored.height), (8, 8));
+}
+
+/// Test notation of squares, moves and results in game records
+#[test]
+fn record_notation() {
+    use record::*;
+    use result::{GameResult, ResultReason};
+
+    assert_eq!(file_name(0), "a");
+    assert_eq!(file_name(25), "z");
+    assert_eq!(file_name(26), "aa");
+    assert_eq!(square_name((4, 6), 8), "e2");
+    assert_eq!(square_name((27, 0), 16), "ab16");
+
+    let mut pieces = helper_get_standard_pieces();
+    pieces.insert("ce".to_string(), piece::Piece::from_parlett("", 0.0, "1*").unwrap());
+    pieces.get_mut("p").unwrap().promotion = Some(piece::Promotion { ranks: vec![8], pieces: vec!["q".to_string()] });
+    let board = board::GameBoard::from_ffen("4k3/1P6/8/8/8/8/4P3/(CE)3K3").unwrap();
+    assert_eq!(move_notation(&GameMove::new(4, 6, 4, 4), &board, &pieces), "e2-e4");
+    assert_eq!(move_notation(&GameMove::new(0, 7, 1, 6), &board, &pieces), "(ce)a1-b2");
+    let mut promotion = GameMove::new(1, 1, 1, 0);
+    promotion.promotion = Some("q".to_string());
+    assert_eq!(move_notation(&promotion, &board, &pieces), "b7-b8=Q");
+
+    let colors = [PieceColor::White, PieceColor::Black];
+    assert_eq!(result_notation(&GameResult::Win(PieceColor::Black, ResultReason::Checkmate), &colors, &Teams::default()), "0-1");
+    assert_eq!(result_notation(&GameResult::Draw(ResultReason::Stalemate), &colors, &Teams::default()), "1/2-1/2");
+    assert_eq!(result_notation(&GameResult::Ongoing, &colors, &Teams::default()), "*");
+    assert_eq!(date_notation(1_000_000_000), "2001.09.09");
+}
+
+/// Test that the bot search finds mates and wins material
+#[test]
+fn search_best_move() {
+    use search::{best_move, SearchLimits};
+    use result::StalemateRule;
+
+    let mut pieces = helper_get_standard_pieces();
+    pieces.get_mut("k").unwrap().kingstatus = true;
+    let colors = [PieceColor::White, PieceColor::Black];
+    let limits = SearchLimits { depth: 2, time: None };
+
+    let board = board::GameBoard::from_ffen("k7/8/1K6/8/8/8/8/7R").unwrap();
+    let roots = helper_root_moves(&board, &pieces);
+    let best = best_move(roots, &pieces, &colors, PieceColor::White, limits, StalemateRule::Draw).unwrap();
+    assert_eq!((best.from, best.to), ((7, 7), (7, 0)));
+
+    let board = board::GameBoard::from_ffen("k7/8/8/3q4/8/8/8/K2R4").unwrap();
+    let roots = helper_root_moves(&board, &pieces);
+    let best = best_move(roots, &pieces, &colors, PieceColor::White, limits, StalemateRule::Draw).unwrap();
+    assert_eq!((best.from, best.to), ((3, 7), (3, 3)));
+}
+
+/// Test that the bot search follows the stalemate rule and winners declared at the root
+#[test]
+fn search_stalemate_and_winner() {
+    use search::{best_move, SearchLimits};
+    use result::StalemateRule;
+
+    let mut pieces = helper_get_standard_pieces();
+    pieces.get_mut("k").unwrap().kingstatus = true;
+    let colors = [PieceColor::White, PieceColor::Black];
+    let limits = SearchLimits { depth: 2, time: None };
+
+    // Nc6 and Nd7 stalemate black
+    let board = board::GameBoard::from_ffen("k7/8/1K6/4N3/8/8/8/8").unwrap();
+    let stalemates = [(2, 2), (3, 1)];
+    let roots = helper_root_moves(&board, &pieces);
+    let best = best_move(roots, &pieces, &colors, PieceColor::White, limits, StalemateRule::Loss).unwrap();
+    assert!(stalemates.contains(&best.to));
+    let roots = helper_root_moves(&board, &pieces);
+    let best = best_move(roots, &pieces, &colors, PieceColor::White, limits, StalemateRule::Win).unwrap();
+    assert!(!stalemates.contains(&best.to));
+
+    let board = board::GameBoard::from_ffen("k7/8/8/8/8/8/8/7K").unwrap();
+    let mut roots = helper_root_moves(&board, &pieces);
+    roots.last_mut().unwrap().winner = Some(PieceColor::White);
+    let winning = roots.last().unwrap().mov.clone();
+    let best = best_move(roots, &pieces, &colors, PieceColor::White, limits, StalemateRule::Draw).unwrap();
+    assert_eq!(best, winning);
+}
+
+/// Test that estimated piece values follow the usual order of chess pieces
+#[test]
+fn estimated_piece_values() {
+    let mut pieces = helper_get_standard_pieces();
+    let value = |symbol: &str| pieces[symbol].estimate_value();
+
+    assert!(value("p") < value("n"));
+    assert!(value("n") < value("r"));
+    assert!(value("b") < value("r"));
+    assert!(value("r") < value("q"));
+    assert!((value("q") - 9.0).abs() < 1.0);
+
+    // Kings aren't counted as material
+    pieces.get_mut("k").unwrap().kingstatus = true;
+    let board = board::GameBoard::from_ffen("4k3/pppp4/8/8/8/8/4P3/3QK3").unwrap();
+    assert_eq!(board.material(PieceColor::White, &pieces), 9.0);
+    assert_eq!(board.material(PieceColor::Black, &pieces), 4.0);
+}
+
+/// Test that legality checked on bitboards agrees with checking on a copy of the board
+#[test]
+fn bitboard_legal_moves() {
+    let mut pieces = helper_get_castling_pieces();
+    pieces.get_mut("k").unwrap().kingstatus = true;
+    let slow_legal_moves = |board: &board::GameBoard| -> Vec<GameMove> {
+        generate_moves(PieceColor::White, &pieces, board, None)
+            .into_iter()
+            .filter(|mov| {
+                let mut nboard = board.clone();
+                nboard.do_move(mov).unwrap();
+                nboard.get_positions_of(&|p: &piece::GamePiece| p.color == PieceColor::White && p.symbol == "k")
+                    .iter()
+                    .all(|(x, y)| !is_checked(PieceColor::White, x, y, &pieces, &nboard))
+            })
+            .collect()
+    };
+    // Pinned pieces and castling on a 64 square board, and a 10x8 board that needs a wide bitboard
+    for ffen in &["r3k2r/8/8/1b6/8/8/3P4/R3K2R", "4k5/4r5/10/10/10/10/4N5/r3K4R"] {
+        let board = board::GameBoard::from_ffen(ffen).unwrap();
+        let moves = legal_moves(PieceColor::White, &pieces, &board, None);
+        assert!(!moves.is_empty());
+        assert_eq!(moves, slow_legal_moves(&board));
     }
-    Err(Error::InvalidMove)
+
+    use bitboard::Bitboard;
+    let mut squares = bitboard::Wide::default();
+    squares.insert(3);
+    squares.insert(200);
+    assert!(squares.contains(200));
+    assert_eq!(squares.pop(), Some(3));
+    assert_eq!(squares.pop(), Some(200));
+    assert_eq!(squares.pop(), None);
+}
+
+/// Test squares attacked by a player, and checks of one of several kings
+#[test]
+fn attacked_squares_and_kings() {
+    let mut pieces = helper_get_standard_pieces();
+    pieces.get_mut("k").unwrap().kingstatus = true;
+
+    // Pawns only attack diagonally, and pieces don't attack squares of their own color
+    let board = board::GameBoard::from_ffen("8/8/8/8/8/8/P7/R7").unwrap();
+    let mut expected = vec![(1, 5)];
+    expected.extend((1..8).map(|x| (x, 7)));
+    assert_eq!(attacked_squares(PieceColor::White, &pieces, &board), expected);
+
+    let board = board::GameBoard::from_ffen("k6k/8/8/8/8/8/8/R7").unwrap();
+    assert!(in_check(PieceColor::Black, &pieces, &board));
+    let board = board::GameBoard::from_ffen("k6k/8/8/8/8/8/8/1R6").unwrap();
+    assert!(!in_check(PieceColor::Black, &pieces, &board));
+
+    // Boards too large for bitboards
+    let board = board::GameBoard::from_ffen(&format!("k15k/{}R16", "17/".repeat(15))).unwrap();
+    assert!(board.width * board.height > 256);
+    assert!(in_check(PieceColor::Black, &pieces, &board));
+    assert_eq!(attacked_squares(PieceColor::White, &pieces, &board).len(), 32);
+}
+
+/// Test pieces of more than two players, moving forward in their own direction
+#[test]
+fn more_players() {
+    let mut pieces = helper_get_standard_pieces();
+    pieces.get_mut("k").unwrap().kingstatus = true;
+    let board = board::GameBoard::from_ffen("{4}p3/4/4/{3}k2{5}r").unwrap();
+    assert_eq!(board.board[0][0].as_ref().unwrap().color, PieceColor::Blue);
+    assert_eq!(board.board[3][3].as_ref().unwrap().color, PieceColor::Green);
+    assert!(board::GameBoard::from_ffen("{9}p").is_err());
+
+    let moves = generate_moves(PieceColor::Blue, &pieces, &board, None);
+    assert_eq!(moves.iter().map(|m| m.to).collect::<Vec<_>>(), [(1, 0), (2, 0)]);
+    // Any other player can give check
+    assert!(in_check(PieceColor::Red, &pieces, &board));
+    assert!(!in_check(PieceColor::Green, &pieces, &board));
+
+    let promotion = piece::Promotion { ranks: vec![8], pieces: vec![] };
+    let board = board::GameBoard::from_ffen("8/8/8/8/8/8/8/8").unwrap();
+    assert!(promotion.on_rank(PieceColor::Blue, (7, 0), &board));
+    assert!(promotion.on_rank(PieceColor::Green, (0, 3), &board));
+    assert!(!promotion.on_rank(PieceColor::Green, (7, 3), &board));
+}
+
+/// Test that color ids match the order of the colors and unknown ids are rejected
+#[test]
+fn color_ids() {
+    assert_eq!(PieceColor::from_id(0), Some(PieceColor::White));
+    assert_eq!(PieceColor::from_id(1), Some(PieceColor::Black));
+    assert_eq!(PieceColor::from_id(PieceColor::Green as u8), Some(PieceColor::Green));
+    assert_eq!(PieceColor::from_id(6), None);
+    assert_eq!("1".parse::<PieceColor>().unwrap(), PieceColor::Black);
+    assert!("6".parse::<PieceColor>().is_err());
+}
+
+/// Test that allied pieces can't capture or check each other
+#[test]
+fn teams() {
+    use record::result_notation;
+    use result::{GameResult, ResultReason};
+
+    let mut pieces = helper_get_standard_pieces();
+    pieces.get_mut("k").unwrap().kingstatus = true;
+    let mut board = board::GameBoard::from_ffen("{4}p3/4/4/{3}k2{5}r").unwrap();
+    board.teams = Teams::new(&[vec![PieceColor::Red, PieceColor::Green]]);
+    assert!(board.teams.allied(PieceColor::Green, PieceColor::Red));
+    assert!(!board.teams.allied(PieceColor::Blue, PieceColor::Red));
+
+    assert!(!in_check(PieceColor::Red, &pieces, &board));
+    let moves = generate_moves(PieceColor::Green, &pieces, &board, None);
+    assert!(!moves.iter().any(|m| m.to == (0, 3)));
+    assert!(!attacked_squares(PieceColor::Green, &pieces, &board).contains(&(0, 3)));
+    // The blue pawn is still an enemy of both
+    assert!(moves.iter().any(|m| m.to == (3, 0)));
+
+    let colors = [PieceColor::Red, PieceColor::Blue, PieceColor::Yellow, PieceColor::Green];
+    let win = GameResult::Win(PieceColor::Green, ResultReason::Checkmate);
+    assert_eq!(result_notation(&win, &colors, &board.teams), "1-0-0-1");
+}
+
+/// Test that voids and missing squares at the end of short rows aren't part of the board
+#[test]
+fn voids() {
+    let mut pieces = helper_get_standard_pieces();
+    pieces.get_mut("k").unwrap().kingstatus = true;
+    let board = board::GameBoard::from_ffen("R-1r/4/2").unwrap();
+    assert_eq!((board.width, board.height), (4, 3));
+    assert_eq!(board.void_squares(), [(1, 0), (2, 2), (3, 2)]);
+    assert!(!board.is_void(1, 2));
+
+    // Sliders stop at voids
+    let targets = |color| generate_moves(color, &pieces, &board, None).iter().map(|m| m.to).collect::<Vec<_>>();
+    assert_eq!(targets(PieceColor::White), [(0, 1), (0, 2)]);
+    assert_eq!(targets(PieceColor::Black), [(2, 0), (3, 1)]);
+    // Leapers can't land on them
+    let board = board::GameBoard::from_ffen("N3/4/1-2").unwrap();
+    let moves = generate_moves(PieceColor::White, &pieces, &board, None);
+    assert_eq!(moves.iter().map(|m| m.to).collect::<Vec<_>>(), [(2, 1)]);
+    // Pieces don't attack through them
+    let board = board::GameBoard::from_ffen("K-r/3").unwrap();
+    assert!(!in_check(PieceColor::White, &pieces, &board));
+    assert!(!legal_moves(PieceColor::White, &pieces, &board, None).is_empty());
+}
+
+/// Test moves on a hex board with two hexagons on each side
+#[test]
+fn hex_board() {
+    use geometry::Geometry;
+    use movement::{Direction, Distance};
+
+    let pieces = helper_get_standard_pieces();
+    let mut board = board::GameBoard::from_ffen("-2/1R1/2").unwrap();
+    board.geometry = Geometry::Hex;
+    let moves = generate_moves(PieceColor::White, &pieces, &board, None);
+    assert_eq!(moves.iter().map(|m| m.to).collect::<Vec<_>>(), [(0, 1), (0, 2), (1, 0), (1, 2), (2, 0), (2, 1)]);
+
+    // Pawns capture on the orthogonal steps at 60 degrees from forward
+    let mut board = board::GameBoard::from_ffen("-1p/pP1/2").unwrap();
+    board.geometry = Geometry::Hex;
+    let moves = generate_moves(PieceColor::White, &pieces, &board, None);
+    assert_eq!(moves.iter().map(|m| m.to).collect::<Vec<_>>(), [(0, 1), (1, 0), (2, 0)]);
+
+    // Knights have twelve moves
+    let knight = Direction::Hippogonal.get_coords(&PieceColor::White, &Distance::Hippogonal { m: 1, n: 2 }, Geometry::Hex);
+    assert_eq!(knight.len(), 12);
+    assert!(knight.contains(&(1, -3)) && knight.contains(&(-3, 1)));
+
+    // The last rank is the edge of each file
+    let promotion = piece::Promotion { ranks: vec![3], pieces: vec![] };
+    assert!(promotion.on_rank(PieceColor::White, (0, 1), &board));
+    assert!(promotion.on_rank(PieceColor::White, (2, 0), &board));
+    assert!(!promotion.on_rank(PieceColor::White, (1, 1), &board));
+}
+
+/// Test that pieces move across the edges of wrapped boards
+#[test]
+fn wrap_around() {
+    use geometry::Wrap;
+
+    let mut pieces = helper_get_standard_pieces();
+    pieces.get_mut("k").unwrap().kingstatus = true;
+    let mut board = board::GameBoard::from_ffen("R3/4").unwrap();
+    board.wrap = Wrap { horizontal: true, vertical: false };
+    // Slides around the board stop before the start square
+    let moves = generate_moves(PieceColor::White, &pieces, &board, None);
+    assert_eq!(moves.iter().map(|m| m.to).collect::<Vec<_>>(), [(0, 1), (1, 0), (2, 0), (3, 0)]);
+
+    let mut board = board::GameBoard::from_ffen("N3/4/4/4").unwrap();
+    board.wrap = Wrap { horizontal: true, vertical: true };
+    let moves = generate_moves(PieceColor::White, &pieces, &board, None);
+    assert_eq!(moves.iter().map(|m| m.to).collect::<Vec<_>>(), [(1, 2), (2, 1), (2, 3), (3, 2)]);
+
+    // The rook attacks the king across the edge
+    let mut board = board::GameBoard::from_ffen("K1P1r/5").unwrap();
+    assert!(!in_check(PieceColor::White, &pieces, &board));
+    board.wrap = Wrap { horizontal: true, vertical: false };
+    assert!(in_check(PieceColor::White, &pieces, &board));
+    assert_eq!(get_checkers(PieceColor::White, &0, &0, &pieces, &board), [(4, 0)]);
+}
+
+/// Test pieces defined with Betza notation
+#[test]
+fn betza_pieces() {
+    let mut pieces = piece::PieceList::new();
+    for (symbol, betza) in &[("p", "fmWfcFifmnD"), ("n", "N"), ("b", "B"), ("r", "R"), ("q", "Q"), ("k", "K")] {
+        pieces.insert(symbol.to_string(), piece::Piece::from_betza(symbol, 1.0, betza).unwrap());
+    }
+    let board = board::GameBoard::from_ffen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap();
+    assert_eq!(perft(3, PieceColor::White, &pieces, &board), 8902);
+    assert!(piece::Piece::from_betza("x", 1.0, "WX").is_err());
+    assert!(piece::Piece::from_betza("x", 1.0, "fW2F").is_ok());
+
+    let targets = |betza: &str, ffen: &str| {
+        let mut pieces = pieces.clone();
+        pieces.insert("x".to_string(), piece::Piece::from_betza("x", 1.0, betza).unwrap());
+        let board = board::GameBoard::from_ffen(ffen).unwrap();
+        generate_moves(PieceColor::White, &pieces, &board, None).iter().map(|m| m.to).collect::<Vec<_>>()
+    };
+    // Narrow and wide knight moves, facing down for black
+    assert_eq!(targets("ffN", "5/5/2X2/5/5"), [(1, 0), (3, 0)]);
+    assert_eq!(targets("fsN", "5/5/2X2/5/5"), [(0, 1), (4, 1)]);
+    // The horse is blocked by the piece next to it
+    assert_eq!(targets("nN", "5/5/1pX2/5/5"), [(1, 0), (1, 4), (3, 0), (3, 4), (4, 1), (4, 3)]);
+    // The cannon moves like a rook and captures by hopping over a piece
+    assert_eq!(targets("mRcpR", "X1P1p/5"), [(0, 1), (1, 0), (4, 0)]);
+    // The grasshopper lands right behind the piece it hops over
+    assert_eq!(targets("gQ", "XP2/1P2/4/4"), [(2, 0), (2, 2)]);
+    // Riders with a limited range
+    assert_eq!(targets("fR2", "3/3/3/1X1"), [(1, 1), (1, 2)]);
+}
+
+/// Test that repeated, grouped and sideways Parlett moves reach the expected squares
+#[test]
+fn parlett_repeat_and_groups() {
+    let targets = |color: PieceColor, parlett: &str, ffen: &str| {
+        let mut pieces = piece::PieceList::new();
+        pieces.insert("p".to_string(), piece::Piece::from_parlett("Pawn", 1.0, "o1>").unwrap());
+        pieces.insert("x".to_string(), piece::Piece::from_parlett("X", 1.0, parlett).unwrap());
+        let board = board::GameBoard::from_ffen(ffen).unwrap();
+        let mut to = generate_moves(color, &pieces, &board, None).iter().map(|m| m.to).collect::<Vec<_>>();
+        to.sort();
+        to
+    };
+    // The nightrider repeats knight leaps in the same direction, both as a repeat and as a group
+    let nightrider = [(1, 5), (2, 3), (2, 6), (3, 1), (4, 5), (6, 4)];
+    assert_eq!(targets(PieceColor::White, "~1/2&", "8/8/8/8/8/8/8/X7"), nightrider);
+    assert_eq!(targets(PieceColor::White, "n(~1/2)", "8/8/8/8/8/8/8/X7"), nightrider);
+    // The repeated leaps stop at the first piece they land on
+    assert_eq!(targets(PieceColor::White, "~1/2&", "8/8/8/2p5/8/8/8/X7"), [(1, 5), (2, 3), (2, 6), (4, 5), (6, 4)]);
+    // Exactly two knight leaps
+    assert_eq!(targets(PieceColor::White, "2(~1/2)", "8/8/8/8/8/8/8/X7"), [(2, 3), (4, 5)]);
+    // Grouped steps are blocked by pieces on the way unless they leap
+    assert_eq!(targets(PieceColor::White, "n(2+)", "5/5/5/p4/X4"), [(2, 4), (4, 4)]);
+    assert_eq!(targets(PieceColor::White, "n(~2+)", "5/5/5/p4/X4"), [(0, 0), (0, 2), (2, 4), (4, 4)]);
+    // Sideways moves are at right angles to the way the piece faces
+    assert_eq!(targets(PieceColor::White, "1=", "3/1X1/3"), [(0, 1), (2, 1)]);
+    assert_eq!(targets(PieceColor::Blue, "1=", "3/1{4}X1/3"), [(1, 0), (1, 2)]);
+}
+
+/// Test that parse errors point at the failing character of the notation
+#[test]
+fn parse_error_positions() {
+    let position = |result: Result<(), crate::Error>| match result {
+        Err(crate::Error::ParlettParse { position, .. }) |
+        Err(crate::Error::BetzaParse { position, .. }) |
+        Err(crate::Error::FFenParse { position, .. }) => position,
+        _ => panic!("expected a parse error"),
+    };
+    assert_eq!(position(piece::Piece::from_parlett("x", 1.0, "n+,~1/x").map(|_| ())), 7);
+    assert_eq!(position(piece::Piece::from_betza("x", 1.0, "WX").map(|_| ())), 2);
+    assert_eq!(position(board::GameBoard::from_ffen("8/{9}p7").map(|_| ())), 4);
+    assert_eq!(position(board::GameBoard::from_ffen("8/p?6").map(|_| ())), 4);
+}
+
+/// Test that rhai board functions return errors for squares off the board or void squares
+#[test]
+fn rhai_board_squares() {
+    use structopt::StructOpt;
+
+    let limits = crate::args::ScriptLimits::from_iter(&["test"]);
+    let env = crate::rules::RulesEnv::new("games/glinski.rhai", &limits).unwrap();
+    let error = |script: &str| env.engine.eval::<rhai::Dynamic>(script).unwrap_err().to_string();
+    assert!(error("get_board().get_piece(-1, 0)").contains("Square (-1, 0) is off the board"));
+    assert!(error("get_board().add_piece(\"white\", \"q\", 100, 0)").contains("Square (100, 0) is off the board"));
+    assert!(error("get_board().add_piece(\"white\", \"q\", 0, 0)").contains("Square (0, 0) is not part of the board"));
+    assert!(env.engine.eval::<rhai::Dynamic>("get_board().add_piece(\"white\", \"q\", 5, 0)").is_ok());
+    assert_eq!(env.rules.borrow().board.borrow().board[0][5].as_ref().unwrap().symbol, "q");
+}
+
+/// Test that rhai functions taking counts return errors for negative numbers
+#[test]
+fn rhai_negative_numbers() {
+    use structopt::StructOpt;
+
+    let limits = crate::args::ScriptLimits::from_iter(&["test"]);
+    let env = crate::rules::RulesEnv::new("games/standard.rhai", &limits).unwrap();
+    let error = |script: &str| env.engine.eval::<rhai::Dynamic>(script).unwrap_err().to_string();
+    assert!(error("rules().set_repetitions(-1)").contains("Repetitions cannot be negative"));
+    assert!(error("rules().set_move_limit(-50)").contains("Move limit cannot be negative"));
+    assert!(error("rules().add_castling(\"k\", \"r\", -2)").contains("Castling distance cannot be negative"));
+    assert!(error("rules().set_promotion(\"p\", [8, -1], [\"q\"])").contains("Promotion ranks cannot be negative"));
+    assert!(env.engine.eval::<rhai::Dynamic>("rules().set_move_limit(50)").is_ok());
+}
+
+/// Test that callbacks that fail or never finish are returned as errors naming the piece and the
+/// line of the callback
+#[test]
+fn failing_callbacks() {
+    let env = helper_rules_env("failing_callbacks", r#"
+let rules = rules();
+rules.add_piece("k", "King", "1*");
+rules.add_piece("q", "Queen", "n*");
+rules.set_kingstatus("k", true);
+rules.create_board("k2/3/QK1");
+rules.after_move("k", |m| get_board().add_piece("white", "q", 10, 10));
+rules.after_move("q", |m| {
+    loop {}
+});
+return rules;
+"#);
+    let rules = env.rules.borrow();
+    let mut scope = rhai::Scope::new();
+    let mut run = |symbol: &str, gamemove: GameMove| crate::rules::run_callbacks(
+        &env.engine, &env.ast, &mut scope, symbol, &rules.pieces[symbol], &gamemove, false);
+    match run("k", GameMove::new(1, 2, 2, 2)) {
+        Err(e @ crate::Error::Callback { .. }) => {
+            let message = e.to_string();
+            assert!(message.contains("\"k\""), "{}", message);
+            assert!(message.contains("line 7"), "{}", message);
+            assert!(message.contains("Square (10, 10) is off the board"), "{}", message);
+            assert!(!message.contains("anon$"), "{}", message);
+        },
+        other => panic!("expected a callback error, got {:?}", other),
+    }
+    match run("q", GameMove::new(0, 2, 0, 1)) {
+        Err(e @ crate::Error::Callback { .. }) => {
+            let message = e.to_string();
+            assert!(message.contains("\"q\""), "{}", message);
+            assert!(message.contains("line 8"), "{}", message);
+            assert!(!message.contains("anon$"), "{}", message);
+        },
+        other => panic!("expected a callback error, got {:?}", other),
+    }
+}
+
+/// Test that the check command reports every kind of problem, and that a missing king is only a
+/// warning
+#[test]
+fn check_problems() {
+    let check = |name: &str, board: &str| crate::commands::problems(&helper_rules_env(name, &format!(r#"
+let rules = rules();
+rules.add_piece("k", "King", "1*");
+rules.add_piece("q", "Queen", "n*");
+rules.add_piece("w", "Wazir pawn", "o1>");
+rules.set_kingstatus("k", true);
+{}
+return rules;
+"#, board)));
+
+    let problems = check("check_no_board", "");
+    assert_eq!(problems.errors, vec!["The rules don't create a board"]);
+
+    let problems = check("check_short_row", "rules.create_board(\"k2/2/K2\");");
+    assert!(problems.errors.contains(&"Row 2 has 2 squares, but the board is 3 squares wide".to_string()));
+
+    let problems = check("check_unknown_piece", "rules.create_board(\"k1x/3/K2\");");
+    assert_eq!(problems.errors, vec!["No piece is defined for \"x\" on c3"]);
+
+    let problems = check("check_no_king", "rules.create_board(\"k2/3/Q2\");");
+    assert!(problems.errors.is_empty());
+    assert_eq!(problems.warnings, vec!["White has no king"]);
+
+    let problems = check("check_missing_callback", "rules.create_board(\"k2/3/QK1\"); rules.after_move(\"q\", Fn(\"missing\"));");
+    assert_eq!(problems.errors, vec!["The after_move callback of \"q\" given on line 7 has no function missing with 1 parameters"]);
+
+    let problems = check("check_stuck_piece", "rules.create_board(\"W1k/3/K2\");");
+    assert_eq!(problems.errors, vec!["\"w\" on a3 can't move from its start square even with no other pieces on the board"]);
+
+    let problems = check("check_no_problems", "rules.create_board(\"k2/3/QK1\");");
+    assert!(problems.errors.is_empty() && problems.warnings.is_empty());
+}
+
+/// Loads rules from a script written to a temporary file named after `name`
+fn helper_rules_env(name: &str, script: &str) -> crate::rules::RulesEnv<'static> {
+    use structopt::StructOpt;
+
+    let path = std::env::temp_dir().join(format!("alfheimr_{}.rhai", name));
+    std::fs::write(&path, script).unwrap();
+    let limits = crate::args::ScriptLimits::from_iter(&["test"]);
+    crate::rules::RulesEnv::new(path.to_str().unwrap(), &limits).unwrap()
+}
+
+/// Returns a list of standard pieces where the king can castle with rooks
+fn helper_get_castling_pieces() -> piece::PieceList {
+    let mut pieces = helper_get_standard_pieces();
+    pieces.get_mut("k").unwrap().castling.push(piece::Castling {
+        partner: "r".to_string(),
+        distance: 2,
+    });
+    pieces
 }
 
 /// Returns a list of standard pieces
@@ -160,3 +861,14 @@ fn helper_get_standard_pieces() -> piece::PieceList {
     pieces.insert("k".to_string(), piece::Piece::from_parlett("King", 100.0, "1*").unwrap());
     return pieces;
 }
+
+/// Makes each legal move of white on a copy of the board, as the game does before a bot searches
+fn helper_root_moves(board: &board::GameBoard, pieces: &piece::PieceList) -> Vec<search::RootMove> {
+    legal_moves(PieceColor::White, pieces, board, None).into_iter()
+        .map(|mov| {
+            let mut nboard = board.clone();
+            nboard.do_move(&mov).unwrap();
+            search::RootMove { mov, board: nboard, winner: None }
+        })
+        .collect()
+}
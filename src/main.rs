@@ -15,50 +15,77 @@ mod rules;
 mod logging;
 /// Main part of game
 mod game;
+/// Hosts multiple games at once
+mod lobby;
+/// Subcommands for testing rules
+mod commands;
 
 use tokio::{
     sync::mpsc,
+    time,
 };
-use rules::RulesEnv;
-use networking::{MsgData, Msg, ClientList};
+use networking::Msg;
 use structopt::StructOpt;
 use error::Error;
-use game::Game;
+use lobby::Lobby;
 
-/// Sets up the game
+/// Sets up the game. Errors, like mistakes in a rules file, are logged before exiting
 #[tokio::main]
-async fn main() -> Result<(), Error> {
+async fn main() {
     let options = args::Arguments::from_args();
     logging::initialize_logging(&options);
-    let rules = RulesEnv::new(&options.game)?;
+    if let Err(e) = run(options).await {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+}
 
+/// Runs a subcommand or hosts games until the server is shut down
+async fn run(options: args::Arguments) -> Result<(), Error> {
+    if let Some(command) = &options.command {
+        return match command {
+            args::Command::Perft(perft) => commands::perft(perft, &options.limits),
+            args::Command::Check(check) => commands::check(check, &options.limits),
+        };
+    }
     let (rx, clients) = networking::handle_connections(options.port).await;
-    let clients_clone = clients.clone();
+    let mut lobby = Lobby::new(clients.clone(), &options);
+    if let Some(file) = &options.resume {
+        lobby.resume_game(file)?;
+    }
+    if !lobby.has_game(lobby::DEFAULT_GAME) {
+        lobby.create_game(lobby::DEFAULT_GAME, &options.game)?;
+    }
+    if let Err(e) = lobby.add_bots(lobby::DEFAULT_GAME, &options.bot).await {
+        error!("Failed to add bots: {}", e);
+    }
+    // The game loop is told to save the games and close the connections on Ctrl-C
+    let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
     ctrlc::set_handler(move || {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        if let Ok(mut clients_unlocked) = clients_clone.lock() {
-            info!("Server is shutting down");
-            for client in clients_unlocked.values_mut() {
-                rt.block_on(client.close("Server is shutting down"));
-            }
-            std::process::exit(1);
-        }
+        shutdown_tx.blocking_send(()).ok();
     })
     .expect("Error setting Ctrl-C handler");
-    debug!("Board: {:?}", rules.rules.borrow().board);
-    game_loop(rx, clients, rules).await;
-    Ok(())
+    game_loop(rx, shutdown_rx, lobby).await;
+    std::process::exit(1);
 }
 
 
-/// Waits for messages from the client and handles the requests
-async fn game_loop(mut rx: mpsc::Receiver<Msg>, clients: ClientList, rules: RulesEnv<'_>) {
-    let mut game = Game::new(clients, rules);
-    while let Some(msg) = rx.recv().await {
-        match msg.data {
-            MsgData::NewConnection => game.new_connection(&msg.addr).await,
-            MsgData::ClosedConnection => game.closed_connection(&msg.addr),
-            MsgData::Data(content) => game.handle_message(&content, &msg.addr).await,
+/// Waits for messages from the client and handles the requests. Games are also checked when a
+/// player runs out of time
+async fn game_loop(mut rx: mpsc::Receiver<Msg>, mut shutdown: mpsc::Receiver<()>, mut lobby: Lobby) {
+    loop {
+        let deadline = lobby.next_deadline();
+        let timeout = time::Instant::from_std(deadline.unwrap_or_else(std::time::Instant::now));
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(msg) => lobby.handle_msg(msg).await,
+                None => break,
+            },
+            _ = time::sleep_until(timeout), if deadline.is_some() => lobby.update_games().await,
+            _ = shutdown.recv() => {
+                lobby.shutdown().await;
+                break;
+            },
         }
     }
 }
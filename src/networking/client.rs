@@ -64,16 +64,16 @@ impl Client {
     }
 
     /// Sends new player msg to client
-    pub async fn new_player(&mut self, color: PieceColor, pieces: &PieceList, name: &str) {
+    pub async fn new_player(&mut self, color: PieceColor, pieces: &PieceList, name: &str, token: Option<String>) {
         self.send_socket(&SocketData::NewClient(
-                new_client(ClientType::Player(color as u8), pieces, name)
+                new_client(ClientType::Player(color as u8), pieces, name, token)
             )).await;
     }
 
     /// Send new spectator msg to client
     pub async fn new_spectator(&mut self, pieces: &PieceList, name: &str) {
         self.send_socket(&SocketData::NewClient(
-                new_client(ClientType::Spectator, pieces, name)
+                new_client(ClientType::Spectator, pieces, name, None)
             )).await;
     }
 }
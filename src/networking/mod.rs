@@ -10,12 +10,12 @@ mod test;
 
 use std::{
     net::SocketAddr,
-    sync::{Arc, Mutex},
+    sync::Arc,
     collections::HashMap,
 };
 use tokio::{
     net::{TcpListener, TcpStream},
-    sync::mpsc,
+    sync::{mpsc, Mutex},
 };
 use tokio_tungstenite::tungstenite::protocol::Message;
 use futures_util::{TryStreamExt, StreamExt};
@@ -45,7 +45,7 @@ pub async fn handle_connections(port: u16) -> (mpsc::Receiver<Msg>, ClientList)
     let clients = Arc::new(Mutex::new(HashMap::new()));
     let (tx, rx) = mpsc::channel(32);
     tokio::spawn(listen_for_connections(tx, clients.clone(), port));
-    return (rx, clients);
+    (rx, clients)
 }
 
 /// Listens for new connections and starts a new listener thread
@@ -74,9 +74,7 @@ async fn create_websocket(tx: mpsc::Sender<Msg>, stream: TcpStream, addr: Socket
     };
     let (outgoing, mut incoming) = ws_stream.split();
     // Adding client to clientlist
-    if let Ok(mut x) = clients.lock() {
-        x.insert(addr, Client::new(outgoing));
-    }
+    clients.lock().await.insert(addr, Client::new(outgoing));
     // Informing main thread of the new connection
     send_msg(&tx, MsgData::NewConnection, addr).await;
     // Listening for new messages on websocket
@@ -89,7 +87,7 @@ async fn create_websocket(tx: mpsc::Sender<Msg>, stream: TcpStream, addr: Socket
             match parse_msg(&data) {
                 Some(parsed_msg) => send_msg(&tx, parsed_msg, addr).await,
                 None => {
-                    if data.len() != 0 {
+                    if !data.is_empty() {
                         warn!("Received invalid message from {}", addr);
                     }
                 },
@@ -6,4 +6,49 @@ use crate::logic::move_gen::GameMove;
 #[serde(tag = "action", content = "data", rename_all = "snake_case")]
 pub enum ReceivedData {
     Move(GameMove),
+    /// Requests the list of games on the server
+    ListGames,
+    /// Creates a new game and joins it
+    CreateGame(NewGame),
+    /// Joins the game with the given name
+    JoinGame(String),
+    /// Leaves the current game
+    LeaveGame,
+    /// Takes back a seat using the session token given when first joining
+    Rejoin(String),
+    /// Gives up the game
+    Resign,
+    /// Offers the other players a draw
+    OfferDraw,
+    /// Accepts a draw offered by another player
+    AcceptDraw,
+    /// Declines a draw offered by another player
+    DeclineDraw,
+    /// Requests the record of the current game
+    GetRecord,
+    /// Adds a bot to the current game
+    AddBot(NewBot),
+}
+
+/// Data for creating a new game
+#[derive(Deserialize, Debug)]
+pub struct NewGame {
+    /// Name of the game
+    pub name: String,
+    /// Name of the rules file in the games directory without the extension
+    pub rules: String,
+}
+
+/// Data for adding a bot to a game
+#[derive(Deserialize, Debug)]
+pub struct NewBot {
+    /// Color the bot plays. The first free seat is taken if none is given
+    #[serde(default)]
+    pub color: Option<u8>,
+    /// Number of moves the bot looks ahead
+    #[serde(default)]
+    pub depth: Option<usize>,
+    /// Seconds the bot can search for a move
+    #[serde(default)]
+    pub time: Option<f64>,
 }
@@ -4,7 +4,9 @@ use crate::{
         color::PieceColor,
         piece::PieceList,
         board::GameBoard,
+        geometry::Geometry,
         move_gen::GameMove,
+        result::ResultReason,
     }
 };
 
@@ -23,8 +25,35 @@ pub enum SocketData {
     Move(Move),
     /// Player Won
     Winner(Winner),
+    /// Game ended in a draw
+    Draw(Draw),
+    /// Player offered or accepted a draw
+    DrawOffer(u8),
+    /// Player declined a draw offer
+    DrawDeclined(u8),
     /// Something went wrong
     Error(String),
+    /// List of games on the server
+    GameList(Vec<GameInfo>),
+    /// Record of the game in a PGN-like format
+    Record(String),
+    /// A bot has taken the seat of a color
+    BotJoined(u8),
+}
+
+/// Summary of a game shown in the game list
+#[derive(Serialize)]
+pub struct GameInfo {
+    /// Name of the game
+    name: String,
+    /// Name of the rules used by the game
+    variant: String,
+    /// Number of connected players
+    players: usize,
+    /// Number of players needed to start the game
+    seats: usize,
+    /// Whether the game has started
+    started: bool,
 }
 
 /// Data sent to clients when someone wins
@@ -32,6 +61,20 @@ pub enum SocketData {
 pub struct Winner {
     /// Winner of the game
     winner: u8,
+    /// Allies of the winner, who win with them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    allies: Vec<u8>,
+    /// How the game was won
+    reason: ResultReason,
+    /// Final board layout
+    board: BoardData,
+}
+
+/// Data sent to clients when the game is drawn
+#[derive(Serialize)]
+pub struct Draw {
+    /// Why the game was drawn
+    reason: ResultReason,
     /// Final board layout
     board: BoardData,
 }
@@ -45,6 +88,21 @@ pub struct Move {
     board: BoardData,
     /// A list of valid moves
     moves: Vec<GameMove>,
+    /// Milliseconds left for each player in turn order, if the game uses a time control
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clocks: Option<Vec<u64>>,
+    /// Total value of the pieces of each player in turn order
+    material: Vec<f32>,
+    /// Squares attacked by the pieces of each player in turn order
+    attacked: Vec<Vec<(usize, usize)>>,
+    /// Ids of the players that have lost while the game goes on
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    eliminated: Vec<u8>,
+    /// Squares that aren't part of the board
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    voids: Vec<(usize, usize)>,
+    /// Shape of the squares of the board, "square" or "hex"
+    geometry: Geometry,
 }
 
 /// Message to new client
@@ -56,6 +114,9 @@ pub struct NewClient {
     client_type: ClientType,
     /// List of piecetypes
     pieces: Vec<(String, String)>,
+    /// Session token used to rejoin the game as the same player
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
 }
 
 /// Type of client
@@ -69,10 +130,11 @@ pub enum ClientType {
 }
 
 /// Creates a `NewClient` object
-pub fn new_client(client_type: ClientType, pieces: &PieceList, name: &str) -> NewClient {
+pub fn new_client(client_type: ClientType, pieces: &PieceList, name: &str, token: Option<String>) -> NewClient {
     NewClient {
         name: String::from(name),
         client_type,
+        token,
         // Generates list of pieces
         pieces: pieces.iter()
             .map(|(key, value)| {
@@ -82,6 +144,17 @@ pub fn new_client(client_type: ClientType, pieces: &PieceList, name: &str) -> Ne
     }
 }
 
+/// Creates a `GameInfo` object
+pub fn game_info(name: &str, variant: &str, players: usize, seats: usize, started: bool) -> GameInfo {
+    GameInfo {
+        name: String::from(name),
+        variant: String::from(variant),
+        players,
+        seats,
+        started,
+    }
+}
+
 /// Generate `BoardData` from `GameBoard`
 pub fn generate_boarddata(board: &GameBoard) -> BoardData {
         board.board.iter()
@@ -94,9 +167,16 @@ pub fn generate_boarddata(board: &GameBoard) -> BoardData {
 }
 
 /// Generates `SocketData::Move`
-pub fn create_move(turn: PieceColor, board: &GameBoard, moves: Vec<GameMove>) -> SocketData {
+pub fn create_move(turn: PieceColor, board: &GameBoard, moves: Vec<GameMove>, clocks: Option<Vec<u64>>,
+                   material: Vec<f32>, attacked: Vec<Vec<(usize, usize)>>, eliminated: Vec<u8>) -> SocketData {
     SocketData::Move(Move{
         moves,
+        clocks,
+        material,
+        attacked,
+        eliminated,
+        voids: board.void_squares(),
+        geometry: board.geometry,
         turn: turn as u8,
         board: generate_boarddata(board),
     })
@@ -105,9 +185,19 @@ pub fn create_move(turn: PieceColor, board: &GameBoard, moves: Vec<GameMove>) ->
 impl SocketData {
 
     /// Generates `SocketData::Winner`
-    pub fn winner(winner: PieceColor, board: &GameBoard) -> Self {
+    pub fn winner(winner: PieceColor, allies: Vec<PieceColor>, reason: ResultReason, board: &GameBoard) -> Self {
         SocketData::Winner(Winner{
             winner: winner as u8,
+            allies: allies.into_iter().map(|color| color as u8).collect(),
+            reason,
+            board: generate_boarddata(board),
+        })
+    }
+
+    /// Generates `SocketData::Draw`
+    pub fn draw(reason: ResultReason, board: &GameBoard) -> Self {
+        SocketData::Draw(Draw{
+            reason,
             board: generate_boarddata(board),
         })
     }
@@ -2,6 +2,7 @@ use std::{
     rc::Rc,
     cell::RefCell,
 };
+use super::Rules;
 use crate::logic::{
     piece::GamePiece,
     board::GameBoard,
@@ -18,11 +19,12 @@ pub fn setup_methods(engine: &mut Engine, board: &SharedBoard) {
     engine.register_type_with_name::<SharedBoard>("GameBoard")
         .register_fn("get_board", move || board_clone.clone())
         .register_fn("contains_piece", contains_piece)
-        .register_result_fn("get_piece", get_piece)
+        .register_fn("get_piece", get_piece)
         .register_fn("add_piece", add_piece)
         .register_fn("remove_piece", remove_piece);
     engine.register_type::<GameMove>()
-        .register_get("points", get_points);
+        .register_get("points", get_points)
+        .register_get("promotion", get_promotion);
 }
 
 /// Checks if a position contains a `GamePiece`
@@ -34,18 +36,34 @@ fn contains_piece(sharedboard: SharedBoard, x: i64, y: i64) -> bool {
     board.board[y as usize][x as usize].is_some()
 }
 
+/// Gets the indices of a square, or an error if it is off the board
+fn square(board: &GameBoard, x: i64, y: i64) -> Result<(usize, usize), Box<EvalAltResult>> {
+    if x < 0 || x >= board.width as i64 || y < 0 || y >= board.height as i64 {
+        return Err(format!("Square ({}, {}) is off the board", x, y).into());
+    }
+    Ok((x as usize, y as usize))
+}
+
 /// Returns `GamePiece` if it exists on the given position
-fn get_piece(board: SharedBoard, x: i64, y: i64) -> Result<GamePiece, Box<EvalAltResult>> {
-    board.borrow_mut().board[y as usize][x as usize].clone().ok_or("No piece".into())
+fn get_piece(sharedboard: SharedBoard, x: i64, y: i64) -> Result<GamePiece, Box<EvalAltResult>> {
+    let board = sharedboard.borrow();
+    let (x, y) = square(&board, x, y)?;
+    board.board[y][x].clone().ok_or_else(|| "No piece".into())
 }
 
-/// Adds a piece to the board
-fn add_piece(board: SharedBoard, color: i64, piece: String, x: i64, y: i64) {
-    board.borrow_mut().board[y as usize][x as usize] = Some(GamePiece{
+/// Adds a piece to the board. The color is given by its name or id
+fn add_piece(sharedboard: SharedBoard, color: Dynamic, piece: String, x: i64, y: i64) -> Result<(), Box<EvalAltResult>> {
+    let mut board = sharedboard.borrow_mut();
+    let (x, y) = square(&board, x, y)?;
+    if board.is_void(x, y) {
+        return Err(format!("Square ({}, {}) is not part of the board", x, y).into());
+    }
+    board.board[y][x] = Some(GamePiece{
         symbol: piece,
-        color: color.into(),
+        color: Rules::color(color)?,
         has_moved: false,
     });
+    Ok(())
 }
 
 /// Removes a piece from the board
@@ -65,3 +83,8 @@ fn get_points(m: &mut GameMove) -> Array {
         Dynamic::from(m.to.1 as i64)
     ]
 }
+
+/// Returns the symbol of the piece promoted to, or `()` if the move isn't a promotion
+fn get_promotion(m: &mut GameMove) -> Dynamic {
+    m.promotion.clone().map_or(Dynamic::UNIT, Dynamic::from)
+}
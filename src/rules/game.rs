@@ -1,10 +1,18 @@
-use super::WinnerState;
-use rhai::Engine;
+use super::{WinnerState, MoveHistory, RhaiResult, Rules};
+use rhai::{Engine, Dynamic, Array};
 
 
 /// Adds general functions to the game
-pub fn setup_functions(engine: &mut Engine, winner: WinnerState) {
-    engine.register_fn("set_winner", move |color: i64| {
-        winner.replace(Some(color.into()));
+pub fn setup_functions(engine: &mut Engine, winner: WinnerState, history: MoveHistory) {
+    engine.register_fn("set_winner", move |color: Dynamic| -> RhaiResult {
+        winner.replace(Some(Rules::color(color)?));
+        Ok(())
+    });
+    let history_clone = history.clone();
+    engine.register_fn("last_move", move || {
+        history_clone.borrow().last().cloned().map_or(Dynamic::UNIT, Dynamic::from)
+    });
+    engine.register_fn("history", move || {
+        history.borrow().iter().cloned().map(Dynamic::from).collect::<Array>()
     });
 }
@@ -7,24 +7,40 @@ mod game;
 
 use crate::{
     Error,
+    args::ScriptLimits,
     logic::{
-        color::PieceColor,
+        color::{PieceColor, Teams},
+        geometry::{Geometry, Wrap},
         piece::{
             Piece,
             PieceList,
+            Promotion,
+            Castling,
+            Callback,
         },
         board::GameBoard,
+        move_gen::GameMove,
+        result::StalemateRule,
+        clock::TimeControl,
     },
 };
 use std::{
     rc::Rc,
-    cell::RefCell,
+    cell::{Cell, RefCell},
+    convert::TryFrom,
+    time::Instant,
 };
-use rhai::{Engine, AST, Scope, FnPtr};
+use rhai::{Engine, EvalAltResult, AST, Scope, FnPtr, FuncArgs, Array, Dynamic, NativeCallContext, module_resolvers::FileModuleResolver};
 
 /// Shared reference to the current winner
 pub type WinnerState = Rc<RefCell<Option<PieceColor>>>;
 
+/// Result of a rules function called from rhai
+type RhaiResult = Result<(), Box<EvalAltResult>>;
+
+/// Shared reference to the moves made in the game
+pub type MoveHistory = Rc<RefCell<Vec<GameMove>>>;
+
 /// Rhai environment for rules
 pub struct RulesEnv<'a> {
     /// User defined rules
@@ -37,15 +53,58 @@ pub struct RulesEnv<'a> {
     pub scope: Scope<'a>,
     /// State of the winner
     pub winner: WinnerState,
+    /// Moves made in the game
+    pub history: MoveHistory,
+    /// Path to the rules file
+    pub path: String,
 }
 
 /// Stores game rules that are generated through rhai.
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub struct Rules {
     pub name: String,
     pub pieces: PieceList,
     pub board: Rc<RefCell<GameBoard>>,
+    /// Ffen string the board was created from
+    pub ffen: String,
     pub colors: Vec<PieceColor>,
+    /// Groups of allied colors
+    pub teams: Vec<Vec<PieceColor>>,
+    /// Shape of the squares of the board
+    pub geometry: Geometry,
+    /// Edges of the board that pieces can move across
+    pub wrap: Wrap,
+    /// Outcome for a player without legal moves that isn't in check
+    pub stalemate: StalemateRule,
+    /// Number of times a position has to occur for the game to be drawn. 0 disables the rule
+    pub repetitions: usize,
+    /// Number of moves by each player without captures or moves of pieces that reset the limit
+    /// before the game is drawn. 0 disables the rule
+    pub move_limit: usize,
+    /// Whether the game is drawn when only kings are left
+    pub insufficient_material: bool,
+    /// Time control for the game
+    pub time_control: Option<TimeControl>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            name: String::new(),
+            pieces: PieceList::new(),
+            board: Rc::default(),
+            ffen: String::new(),
+            colors: vec![],
+            teams: vec![],
+            geometry: Geometry::Square,
+            wrap: Wrap::default(),
+            stalemate: StalemateRule::Draw,
+            repetitions: 3,
+            move_limit: 50,
+            insufficient_material: true,
+            time_control: None,
+        }
+    }
 }
 
 /// Shared reference to rules.
@@ -54,45 +113,168 @@ pub type SharedRules = Rc<RefCell<Rules>>;
 impl RulesEnv<'_> {
 
     /// Creates a new instance of a `RulesEnv` object from a lua script
-    pub fn new(config: &str) -> Result<Self, Error> {
+    pub fn new(config: &str, limits: &ScriptLimits) -> Result<Self, Error> {
         // Setting up engine
         let mut engine = Engine::new();
+        // Modules are imported relative to the directory the server runs in
+        engine.set_module_resolver(FileModuleResolver::new_with_path("."));
+        limit_engine(&mut engine, limits);
         engine.register_type::<Rules>()
             .register_fn("rules", SharedRules::default)
             .register_fn("set_name", Rules::set_name)
+            .register_fn("set_colors", Rules::set_colors)
+            .register_fn("set_teams", Rules::set_teams)
             .register_fn("add_piece", Rules::add_piece)
+            .register_fn("add_piece", Rules::add_piece_with_value)
+            .register_fn("add_piece", |rules: SharedRules, id: &str, name: &str, parlett: &str, value: i64|
+                         Rules::add_piece_with_value(rules, id, name, parlett, value as f64))
+            .register_fn("add_piece_betza", Rules::add_piece_betza)
+            .register_fn("add_piece_betza", Rules::add_piece_betza_with_value)
+            .register_fn("add_piece_betza", |rules: SharedRules, id: &str, name: &str, betza: &str, value: i64|
+                         Rules::add_piece_betza_with_value(rules, id, name, betza, value as f64))
+            .register_fn("set_value", Rules::set_value)
+            .register_fn("set_value", |rules: SharedRules, piece: &str, value: i64|
+                         Rules::set_value(rules, piece, value as f64))
+            .register_fn("set_geometry", Rules::set_geometry)
+            .register_fn("set_wrap", Rules::set_wrap)
             .register_fn("create_board", Rules::create_board)
             .register_fn("after_move", Rules::after_move)
             .register_fn("after_take", Rules::after_take)
             .register_fn("add_moves", Rules::add_moves)
-            .register_fn("set_kingstatus", Rules::set_kingstatus);
+            .register_fn("set_kingstatus", Rules::set_kingstatus)
+            .register_fn("set_promotion", Rules::set_promotion)
+            .register_fn("add_castling", Rules::add_castling)
+            .register_fn("set_en_passant", Rules::set_en_passant)
+            .register_fn("set_stalemate", Rules::set_stalemate)
+            .register_fn("set_repetitions", Rules::set_repetitions)
+            .register_fn("set_move_limit", Rules::set_move_limit)
+            .register_fn("set_resets_move_limit", Rules::set_resets_move_limit)
+            .register_fn("set_insufficient_material", Rules::set_insufficient_material)
+            .register_fn("set_time_control", Rules::set_time_control);
         // Retrieving information from engine
-        let ast = engine.compile_file(config.into())?;
-        let rules = engine.eval_ast::<SharedRules>(&ast)?;
+        let rules_error = |error| Error::Rules { path: config.to_string(), error };
+        let ast = engine.compile_file(config.into()).map_err(rules_error)?;
+        CALL_START.with(|start| start.set(Instant::now()));
+        let rules = engine.eval_ast::<SharedRules>(&ast).map_err(rules_error)?;
         // Creating game variables
         let winner = Rc::new(RefCell::new(None));
+        let history = Rc::new(RefCell::new(vec![]));
         // Adding game functions
-        game::setup_functions(&mut engine, winner.clone());
+        game::setup_functions(&mut engine, winner.clone(), history.clone());
         board::setup_methods(&mut engine, &rules.borrow().board);
         piece::setup_methods(&mut engine);
         // Return Self
-        rules.borrow_mut().colors = vec![PieceColor::White, PieceColor::Black];
-        return Ok(RulesEnv {
+        if rules.borrow().colors.is_empty() {
+            rules.borrow_mut().colors = vec![PieceColor::White, PieceColor::Black];
+        }
+        rules.borrow().board.borrow_mut().teams = Teams::new(&rules.borrow().teams);
+        rules.borrow().board.borrow_mut().geometry = rules.borrow().geometry;
+        rules.borrow().board.borrow_mut().wrap = rules.borrow().wrap;
+        Ok(RulesEnv {
             ast,
             engine,
-            rules: rules,
+            rules,
             scope: Scope::new(),
-            winner: winner,
-        });
+            winner,
+            history,
+            path: config.to_string(),
+        })
     }
 }
 
+/// Runs the `after_move` and `after_take` callbacks of the piece that made `gamemove`. Returns an
+/// error naming the first callback that fails
+pub fn run_callbacks(engine: &Engine, ast: &AST, scope: &mut Scope, symbol: &str, piece: &Piece,
+                     gamemove: &GameMove, take: bool) -> Result<(), Error> {
+    let after_take = piece.after_take.as_ref().filter(|_| take);
+    let callbacks = [("after_move", piece.after_move.as_ref()), ("after_take", after_take)];
+    for (callback, function) in callbacks.iter() {
+        if let Some(function) = function {
+            call_fn(engine, scope, ast, &function.name, (gamemove.clone(), ))
+                .map(|_| ())
+                .map_err(|error| Error::Callback {
+                    callback,
+                    piece: symbol.to_string(),
+                    line: function.line,
+                    error,
+                })?;
+        }
+    }
+    Ok(())
+}
+
+thread_local! {
+    /// When the running rhai script or callback was started
+    static CALL_START: Cell<Instant> = Cell::new(Instant::now());
+}
+
+/// Calls a rhai function, counting the time limit of the engine from the start of the call
+pub fn call_fn(engine: &Engine, scope: &mut Scope, ast: &AST, name: &str, args: impl FuncArgs) -> Result<Dynamic, Box<EvalAltResult>> {
+    CALL_START.with(|start| start.set(Instant::now()));
+    engine.call_fn(scope, ast, name, args)
+}
+
+/// Stops scripts and callbacks that run for too long or use too much memory. Time is counted from
+/// the start of each script or call, which is set before running it
+fn limit_engine(engine: &mut Engine, limits: &ScriptLimits) {
+    engine.set_max_operations(limits.max_operations)
+        .set_max_call_levels(limits.max_call_depth)
+        .set_max_expr_depths(limits.max_expr_depth, limits.max_expr_depth)
+        .set_max_string_size(limits.max_script_size)
+        .set_max_array_size(limits.max_script_size)
+        .set_max_map_size(limits.max_script_size);
+    let time = limits.script_time;
+    engine.on_progress(move |_| {
+        if CALL_START.with(Cell::get).elapsed() > time {
+            return Some(format!("Ran for more than {:?}", time).into());
+        }
+        None
+    });
+}
+
 impl Rules {
 
-    /// Adds a new piece from a parlett string to the rules
-    fn add_piece(rules: Rc<RefCell<Rules>>, id: &str, name: &str, parlett: &str) {
-        let piece = Piece::from_parlett(name, 0.0, parlett).unwrap();
+    /// Adds a new piece from a parlett string to the rules. The value of the piece is estimated
+    /// from its moves
+    fn add_piece(rules: Rc<RefCell<Rules>>, id: &str, name: &str, parlett: &str) -> RhaiResult {
+        let mut piece = Piece::from_parlett(name, 0.0, parlett).map_err(|e| e.to_string())?;
+        piece.value = piece.estimate_value();
         rules.borrow_mut().pieces.insert(id.to_string(), piece);
+        Ok(())
+    }
+
+    /// Adds a new piece from a parlett string with the given value to the rules
+    fn add_piece_with_value(rules: Rc<RefCell<Rules>>, id: &str, name: &str, parlett: &str, value: f64) -> RhaiResult {
+        let piece = Piece::from_parlett(name, value as f32, parlett).map_err(|e| e.to_string())?;
+        rules.borrow_mut().pieces.insert(id.to_string(), piece);
+        Ok(())
+    }
+
+    /// Adds a new piece from Betza notation to the rules. The value of the piece is estimated
+    /// from its moves
+    fn add_piece_betza(rules: Rc<RefCell<Rules>>, id: &str, name: &str, betza: &str) -> RhaiResult {
+        let mut piece = Piece::from_betza(name, 0.0, betza).map_err(|e| e.to_string())?;
+        piece.value = piece.estimate_value();
+        rules.borrow_mut().pieces.insert(id.to_string(), piece);
+        Ok(())
+    }
+
+    /// Adds a new piece from Betza notation with the given value to the rules
+    fn add_piece_betza_with_value(rules: Rc<RefCell<Rules>>, id: &str, name: &str, betza: &str, value: f64) -> RhaiResult {
+        let piece = Piece::from_betza(name, value as f32, betza).map_err(|e| e.to_string())?;
+        rules.borrow_mut().pieces.insert(id.to_string(), piece);
+        Ok(())
+    }
+
+    /// Gets a piece that has been added to the rules
+    fn piece_mut(&mut self, id: &str) -> Result<&mut Piece, Box<EvalAltResult>> {
+        self.pieces.get_mut(id).ok_or_else(|| Error::UnknownPiece(id.to_string()).to_string().into())
+    }
+
+    /// Sets the value of a piece, used by bots and for the material balance
+    fn set_value(rules: Rc<RefCell<Rules>>, piece: &str, value: f64) -> RhaiResult {
+        rules.borrow_mut().piece_mut(piece)?.value = value as f32;
+        Ok(())
     }
 
     /// Sets name of ruleset
@@ -100,27 +282,162 @@ impl Rules {
         rules.borrow_mut().name = String::from(name);
     }
 
+    /// Sets the colors of the players in the order they take turns. Colors are given by name, like
+    /// "red", or by id
+    fn set_colors(rules: Rc<RefCell<Rules>>, colors: Array) -> RhaiResult {
+        rules.borrow_mut().colors = colors.into_iter().map(Rules::color).collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Sets which colors play together, given as arrays of colors like `[["white", "yellow"],
+    /// ["black", "red"]]`. Allied pieces can't capture or check each other, and a team wins
+    /// when every other player has lost
+    fn set_teams(rules: Rc<RefCell<Rules>>, teams: Array) -> RhaiResult {
+        rules.borrow_mut().teams = teams.into_iter()
+            .map(|team| match team.try_cast::<Array>() {
+                Some(team) => team.into_iter().map(Rules::color).collect(),
+                None => Err("Teams have to be arrays of colors".into()),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Gets a color from its name or id
+    fn color(color: Dynamic) -> Result<PieceColor, Box<EvalAltResult>> {
+        let color = match color.as_int() {
+            Ok(id) => u8::try_from(id).ok()
+                .and_then(PieceColor::from_id)
+                .ok_or_else(|| Error::ColorParse(id.to_string())),
+            Err(_) => color.to_string().parse(),
+        };
+        color.map_err(|e| e.to_string().into())
+    }
+
+    /// Sets the shape of the squares of the board, either "square" or "hex". Each line of the
+    /// ffen string of a hex board is a row of its axial coordinates, with voids outside the hexagon
+    fn set_geometry(rules: Rc<RefCell<Rules>>, geometry: &str) -> RhaiResult {
+        rules.borrow_mut().geometry = geometry.parse().map_err(|e: Error| e.to_string())?;
+        Ok(())
+    }
+
+    /// Sets whether pieces can move across the left and right edges, and the top and bottom
+    /// edges, of the board to the opposite side
+    fn set_wrap(rules: Rc<RefCell<Rules>>, horizontal: bool, vertical: bool) {
+        rules.borrow_mut().wrap = Wrap { horizontal, vertical };
+    }
+
     /// Creates a new board from a ffen string
-    fn create_board(rules: Rc<RefCell<Rules>>, ffen: &str) {
-        rules.borrow_mut().board = Rc::new(RefCell::new(GameBoard::from_ffen(ffen).unwrap()));
+    fn create_board(rules: Rc<RefCell<Rules>>, ffen: &str) -> RhaiResult {
+        let board = GameBoard::from_ffen(ffen).map_err(|e| e.to_string())?;
+        let mut rules = rules.borrow_mut();
+        rules.board = Rc::new(RefCell::new(board));
+        rules.ffen = ffen.to_string();
+        Ok(())
     }
 
     /// Adds a new function (`f`) to be run after a move to `piece`
-    fn after_move(rules: Rc<RefCell<Rules>>, piece: &str, f: FnPtr) {
-        rules.borrow_mut().pieces.get_mut(piece).unwrap().after_move = Some(f.fn_name().to_string());
+    fn after_move(context: NativeCallContext, rules: Rc<RefCell<Rules>>, piece: &str, f: FnPtr) -> RhaiResult {
+        rules.borrow_mut().piece_mut(piece)?.after_move = Some(Rules::callback(&context, &f));
+        Ok(())
     }
 
-    fn after_take(rules: Rc<RefCell<Rules>>, piece: &str, f: FnPtr) {
-        rules.borrow_mut().pieces.get_mut(piece).unwrap().after_take = Some(f.fn_name().to_string());
+    fn after_take(context: NativeCallContext, rules: Rc<RefCell<Rules>>, piece: &str, f: FnPtr) -> RhaiResult {
+        rules.borrow_mut().piece_mut(piece)?.after_take = Some(Rules::callback(&context, &f));
+        Ok(())
     }
 
     /// Adds additional moves to a piece
-    fn add_moves(rules: Rc<RefCell<Rules>>, piece: &str, f: FnPtr) {
-        rules.borrow_mut().pieces.get_mut(piece).unwrap().extra_moves = Some(f.fn_name().to_string());
+    fn add_moves(context: NativeCallContext, rules: Rc<RefCell<Rules>>, piece: &str, f: FnPtr) -> RhaiResult {
+        rules.borrow_mut().piece_mut(piece)?.extra_moves = Some(Rules::callback(&context, &f));
+        Ok(())
+    }
+
+    /// Gets the function of a callback and the line it is given on, which is used in errors
+    /// instead of the generated names of anonymous functions
+    fn callback(context: &NativeCallContext, f: &FnPtr) -> Callback {
+        Callback {
+            name: f.fn_name().to_string(),
+            line: context.call_position().line().unwrap_or_default(),
+        }
     }
 
     /// Sets a piece to be treated as a king
-    fn set_kingstatus(rules: Rc<RefCell<Rules>>, piece: &str, value: bool) {
-        rules.borrow_mut().pieces.get_mut(piece).unwrap().kingstatus = value;
+    fn set_kingstatus(rules: Rc<RefCell<Rules>>, piece: &str, value: bool) -> RhaiResult {
+        rules.borrow_mut().piece_mut(piece)?.kingstatus = value;
+        Ok(())
+    }
+
+    /// Makes a piece promote to one of `pieces` when it reaches one of `ranks`. Ranks are
+    /// counted from the players own side, so 8 is the last rank on a standard board
+    fn set_promotion(rules: Rc<RefCell<Rules>>, piece: &str, ranks: Array, pieces: Array) -> RhaiResult {
+        let promotion = Promotion {
+            ranks: ranks.iter()
+                .map(|rank| match rank.as_int() {
+                    Ok(rank) => usize::try_from(rank).map_err(|_| "Promotion ranks cannot be negative"),
+                    Err(_) => Err("Promotion ranks have to be numbers"),
+                })
+                .collect::<Result<_, _>>()?,
+            pieces: pieces.into_iter()
+                .map(|symbol| symbol.into_string())
+                .collect::<Result<_, _>>()
+                .map_err(|_| "Promotion pieces have to be strings")?,
+        };
+        rules.borrow_mut().piece_mut(piece)?.promotion = Some(promotion);
+        Ok(())
+    }
+
+    /// Lets `piece` castle with `partner` by moving `distance` squares towards it
+    fn add_castling(rules: Rc<RefCell<Rules>>, piece: &str, partner: &str, distance: i64) -> RhaiResult {
+        let distance = usize::try_from(distance).map_err(|_| "Castling distance cannot be negative")?;
+        rules.borrow_mut().piece_mut(piece)?.castling.push(Castling {
+            partner: partner.to_string(),
+            distance,
+        });
+        Ok(())
+    }
+
+    /// Lets a piece capture and be captured en passant
+    fn set_en_passant(rules: Rc<RefCell<Rules>>, piece: &str, value: bool) -> RhaiResult {
+        rules.borrow_mut().piece_mut(piece)?.en_passant = value;
+        Ok(())
+    }
+
+    /// Sets the outcome of a stalemate for the stalemated player, either "draw", "loss" or "win"
+    fn set_stalemate(rules: Rc<RefCell<Rules>>, rule: &str) -> RhaiResult {
+        rules.borrow_mut().stalemate = StalemateRule::from_str(rule)
+            .ok_or_else(|| Error::StalemateParse(rule.to_string()).to_string())?;
+        Ok(())
+    }
+
+    /// Sets how many times a position has to occur before the game is drawn
+    fn set_repetitions(rules: Rc<RefCell<Rules>>, repetitions: i64) -> RhaiResult {
+        rules.borrow_mut().repetitions = usize::try_from(repetitions)
+            .map_err(|_| "Repetitions cannot be negative")?;
+        Ok(())
+    }
+
+    /// Sets how many moves each player can make without captures or moves of pieces that reset
+    /// the limit before the game is drawn
+    fn set_move_limit(rules: Rc<RefCell<Rules>>, moves: i64) -> RhaiResult {
+        rules.borrow_mut().move_limit = usize::try_from(moves)
+            .map_err(|_| "Move limit cannot be negative")?;
+        Ok(())
+    }
+
+    /// Sets whether moving a piece resets the move limit, like pawn moves in chess
+    fn set_resets_move_limit(rules: Rc<RefCell<Rules>>, piece: &str, value: bool) -> RhaiResult {
+        rules.borrow_mut().piece_mut(piece)?.resets_move_limit = value;
+        Ok(())
+    }
+
+    /// Sets whether the game is drawn when only kings are left
+    fn set_insufficient_material(rules: Rc<RefCell<Rules>>, value: bool) {
+        rules.borrow_mut().insufficient_material = value;
+    }
+
+    /// Sets the time control from a string like "300+2", "300d2" or "30/move"
+    fn set_time_control(rules: Rc<RefCell<Rules>>, time_control: &str) -> RhaiResult {
+        rules.borrow_mut().time_control = Some(time_control.parse().map_err(|e: Error| e.to_string())?);
+        Ok(())
     }
 }